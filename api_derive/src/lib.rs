@@ -11,12 +11,23 @@ pub fn derive_db_fields(attr: TokenStream, item: TokenStream) -> TokenStream {
     let struct_ident = &inputs.ident;
     let input = syn::parse_macro_input!(attr as syn::AttributeArgs);
     let mut with_extras = true;
+    let mut with_timestamps = false;
+    let mut with_soft_delete = false;
     for nmeta in &input {
-        if let syn::NestedMeta::Meta(syn::Meta::Path(thing)) = nmeta {
-            if let Some(ident) = thing.get_ident() {
-                if "DropExtra".eq(&ident.to_string()) {
-                    with_extras = false;
-                }
+        // a misspelled option must not turn soft deletes into hard ones
+        match nmeta {
+            syn::NestedMeta::Meta(syn::Meta::Path(thing)) if thing.is_ident("DropExtra") => {
+                with_extras = false
+            }
+            syn::NestedMeta::Meta(syn::Meta::Path(thing)) if thing.is_ident("Timestamps") => {
+                with_timestamps = true
+            }
+            syn::NestedMeta::Meta(syn::Meta::Path(thing)) if thing.is_ident("SoftDelete") => {
+                with_soft_delete = true
+            }
+            _ => {
+                let msg = "Unknown derive_db_fields option, expected DropExtra, Timestamps or SoftDelete.";
+                return syn::Error::new_spanned(nmeta, msg).to_compile_error().into();
             }
         }
     }
@@ -35,7 +46,7 @@ pub fn derive_db_fields(attr: TokenStream, item: TokenStream) -> TokenStream {
                     let mut stream = proc_macro::TokenStream::new();
                     let flds_full: proc_macro::TokenStream = quote!(
                     #[serde(skip_serializing_if = "String::is_empty", default)]
-                    #[validate(non_control_character, length(max = 254))]
+                    #[validate(non_control_character, length(max = 254), custom = "api_tools::validate_key")]
                     pub _key: String,
                     // #[serde(skip_serializing_if = "String::is_empty", default)]
                     // #[validate(non_control_character)]
@@ -47,12 +58,32 @@ pub fn derive_db_fields(attr: TokenStream, item: TokenStream) -> TokenStream {
                     #[validate(non_control_character)]
                     pub _old_rev: String,
                 ).into();
+                    // RFC 3339, set by the handlers, see `DbFields::stamp_created`
+                    let timestamp_flds: proc_macro::TokenStream = quote!(
+                        #[serde(skip_serializing_if = "String::is_empty", default)]
+                        pub created_at: String,
+                        #[serde(skip_serializing_if = "String::is_empty", default)]
+                        pub updated_at: String,
+                    )
+                    .into();
+                    // RFC 3339, set by `delete_` instead of removing the document
+                    let soft_delete_fld: proc_macro::TokenStream = quote!(
+                        #[serde(skip_serializing_if = "String::is_empty", default)]
+                        pub deleted_at: String,
+                    )
+                    .into();
                     let extras_fld: proc_macro::TokenStream = quote!(
                         #[serde(flatten)]
                         pub extra: std::collections::HashMap<String, serde_json::Value>,
                     )
                     .into();
                     stream.extend(flds_full);
+                    if with_timestamps {
+                        stream.extend(timestamp_flds);
+                    }
+                    if with_soft_delete {
+                        stream.extend(soft_delete_fld);
+                    }
                     if with_extras {
                         stream.extend(extras_fld);
                     }
@@ -66,133 +97,473 @@ pub fn derive_db_fields(attr: TokenStream, item: TokenStream) -> TokenStream {
             }
         })
         .collect();
+    if with_soft_delete {
+        // tells the derives to mark documents deleted instead of removing them
+        new_struct = with_struct_attr(new_struct, quote!(#[api(soft_delete)]).into());
+    }
+    let mut stamp_created = vec![];
+    let mut stamp_updated = vec![];
+    let mut updated_patch = vec![];
+    if with_timestamps {
+        stamp_created.push(quote!(
+            self.created_at = now.to_owned();
+            self.updated_at = now.to_owned();
+        ));
+        stamp_updated.push(quote!(
+            // the stored one is kept, clients can't forge it
+            self.created_at.clear();
+            self.updated_at = now.to_owned();
+        ));
+        updated_patch.push(quote!(
+            patch.insert("updated_at".to_owned(), serde_json::Value::from(now));
+        ));
+    }
+    if with_soft_delete {
+        // only `delete_` and `restore_` change it
+        stamp_created.push(quote!(self.deleted_at.clear();));
+        stamp_updated.push(quote!(self.deleted_at.clear();));
+    }
+    let stamp_fns = if stamp_created.is_empty() {
+        quote!()
+    } else {
+        quote!(
+            fn stamp_created(&mut self, now: &str) {
+                #(#stamp_created)*
+            }
+
+            fn stamp_updated(&mut self, now: &str) {
+                #(#stamp_updated)*
+            }
+        )
+    };
+    let patch_fns = if updated_patch.is_empty() {
+        quote!()
+    } else {
+        quote!(
+            fn updated_patch(now: &str) -> serde_json::Map<String, serde_json::Value> {
+                let mut patch = serde_json::Map::new();
+                #(#updated_patch)*
+                patch
+            }
+        )
+    };
+    let kept_fns = if with_timestamps {
+        quote!(
+            fn kept_fields() -> &'static [&'static str] {
+                &["created_at"]
+            }
+        )
+    } else {
+        quote!()
+    };
     let impl_fns: proc_macro::TokenStream = quote!(
         impl api_tools::DbFields for #struct_ident {
             fn _key(&self) -> String {
                 self._key.clone()
             }
+            #stamp_fns
+            #patch_fns
+            #kept_fns
         }
     )
     .into();
     new_struct.extend(impl_fns);
+    new_struct.extend(TokenStream::from(configure_fns(&inputs, with_soft_delete)));
     new_struct
 }
 
-#[proc_macro_derive(GetAll, attributes(author))]
+/// Adds `attr` after the attributes of the struct, so it follows the `#[derive(..)]` declaring it.
+fn with_struct_attr(item: TokenStream, attr: TokenStream) -> TokenStream {
+    let mut tokens = item.into_iter().collect::<Vec<_>>();
+    let mut idx = 0;
+    while let (
+        Some(proc_macro::TokenTree::Punct(punct)),
+        Some(proc_macro::TokenTree::Group(group)),
+    ) = (tokens.get(idx), tokens.get(idx + 1))
+    {
+        if punct.as_char() != '#' || group.delimiter() != proc_macro::Delimiter::Bracket {
+            break;
+        }
+        idx += 2;
+    }
+    tokens.splice(idx..idx, attr);
+    tokens.into_iter().collect()
+}
+
+/// `configure` registers the handlers of the derived operations at `/post`, and at `/{author}/post` with an `#[author]`.
+/// Derives can't see each other, so this is done here, `#[derive_db_fields]` has to precede `#[derive(..)]`,
+/// the derives reject it otherwise, see `check_struct_attrs`.
+fn configure_fns(inputs: &syn::ItemStruct, soft_delete: bool) -> proc_macro2::TokenStream {
+    let derived = derived_names(&inputs.attrs);
+    let has = |name: &str| derived.iter().any(|d| d == name);
+    let operations = ["GetAll", "Fetch", "Create", "Update", "Replace", "Delete", "Upsert"];
+    if !operations.iter().any(|op| has(op)) {
+        return quote!();
+    }
+
+    let struct_ident = &inputs.ident;
+    let names = ApiNames::new(struct_ident, &inputs.attrs);
+    let path = &names.path;
+    // the scoped routes have a parameter for each author, e.g. `/{org}/{uid}/post`
+    let author_params = author_fields(inputs)
+        .unwrap_or_default()
+        .iter()
+        .map(|author| format!("/{{{}}}", author.param))
+        .collect::<String>();
+    let mut scopes = vec![String::new()];
+    if !author_params.is_empty() {
+        scopes.push(author_params.clone());
+    }
+    let handler = |op: &str| format_ident!("{}_{}", op, names.data_name);
+
+    let mut services = vec![];
+    let mut collection_routes = vec![];
+    if has("GetAll") {
+        let list = handler("list");
+        collection_routes.push(quote!(.route(actix_web::web::get().to(#list))));
+    }
+    if has("Create") {
+        let create = handler("create");
+        collection_routes.push(quote!(.route(actix_web::web::post().to(#create))));
+    }
+    if has("Upsert") {
+        let upsert = handler("upsert");
+        collection_routes.push(quote!(.route(actix_web::web::put().to(#upsert))));
+    }
+    if !collection_routes.is_empty() {
+        services.push(quote!(
+            cfg.service(actix_web::web::resource(base.as_str()) #(#collection_routes)*);
+        ));
+    }
+    if has("GetAll") {
+        let count = handler("count");
+        services.push(quote!(
+            cfg.service(
+                actix_web::web::resource(format!("{}/count", base))
+                    .route(actix_web::web::get().to(#count)),
+            );
+        ));
+    }
+    let mut bulk_routes = vec![];
+    for (op, handler_op, method) in &[
+        ("Create", "bulk_create", quote!(post)),
+        ("Update", "bulk_update", quote!(patch)),
+        ("Delete", "bulk_delete", quote!(delete)),
+    ] {
+        if has(op) {
+            let fn_name = handler(handler_op);
+            bulk_routes.push(quote!(.route(actix_web::web::#method().to(#fn_name))));
+        }
+    }
+    if !bulk_routes.is_empty() {
+        services.push(quote!(
+            cfg.service(actix_web::web::resource(format!("{}/bulk", base)) #(#bulk_routes)*);
+        ));
+    }
+    let mut document_routes = vec![];
+    for (op, handler_op, method) in &[
+        ("Fetch", "fetch", quote!(get)),
+        ("Update", "update", quote!(patch)),
+        ("Replace", "replace", quote!(put)),
+        ("Delete", "delete", quote!(delete)),
+    ] {
+        if has(op) {
+            let fn_name = handler(handler_op);
+            document_routes.push(quote!(.route(actix_web::web::#method().to(#fn_name))));
+        }
+    }
+    if !document_routes.is_empty() {
+        services.push(quote!(
+            cfg.service(actix_web::web::resource(format!("{}/{{key}}", base)) #(#document_routes)*);
+        ));
+    }
+    if soft_delete && has("Delete") {
+        let restore = handler("restore");
+        services.push(quote!(
+            cfg.service(
+                actix_web::web::resource(format!("{}/{{key}}/restore", base))
+                    .route(actix_web::web::post().to(#restore)),
+            );
+        ));
+    }
+
+    let doc = format!(
+        "Registers the derived handlers of {} at `/{}`{}.",
+        struct_ident,
+        path,
+        if author_params.is_empty() {
+            String::new()
+        } else {
+            format!(" and `{}/{}`", author_params, path)
+        }
+    );
+    quote!(
+        impl #struct_ident {
+            #[doc = #doc]
+            pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
+                Self::configure_with_prefix(cfg, "")
+            }
+
+            /// Same as `configure`, with every route under `prefix`, e.g. `/api`.
+            pub fn configure_with_prefix(cfg: &mut actix_web::web::ServiceConfig, prefix: &str) {
+                let prefix = prefix.trim_end_matches('/');
+                for scope in &[#(#scopes),*] {
+                    let base = format!("{}{}/{}", prefix, scope, #path);
+                    #(#services)*
+                }
+            }
+        }
+    )
+}
+
+/// Rejects a `#[derive_db_fields]` after the `#[derive(..)]`, the derives would miss its fields
+/// and `configure` wouldn't be generated, derives only see the attribute macros which follow them.
+/// Also rejects unknown `#[api(..)]` entries, a misspelled `permissions` must not drop the check.
+fn check_struct_attrs(attrs: &[syn::Attribute]) -> syn::Result<()> {
+    if let Some(attr) =
+        attrs.iter().find(|a| a.path.segments.last().is_some_and(|s| s.ident == "derive_db_fields"))
+    {
+        return Err(syn::Error::new_spanned(
+            attr,
+            "#[derive_db_fields] has to precede #[derive(..)].",
+        ));
+    }
+    for attr in attrs.iter().filter(|a| a.path.is_ident("api")) {
+        let list = match attr.parse_meta()? {
+            syn::Meta::List(list) => list,
+            other => return Err(syn::Error::new_spanned(other, "Expected #[api(..)].")),
+        };
+        for nested in list.nested {
+            let known = match &nested {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) => {
+                    ["collection", "path", "default_sort", "key"]
+                        .iter()
+                        .any(|name| nv.path.is_ident(name))
+                        && matches!(nv.lit, syn::Lit::Str(_))
+                }
+                syn::NestedMeta::Meta(syn::Meta::List(list)) => list.path.is_ident("permissions"),
+                // `soft_delete` is added by `#[derive_db_fields(SoftDelete)]`
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) => {
+                    path.is_ident("hooks") || path.is_ident("soft_delete")
+                }
+                syn::NestedMeta::Lit(_) => false,
+            };
+            if !known {
+                return Err(syn::Error::new_spanned(
+                    nested,
+                    "Unknown api attribute, expected collection = \"..\", path = \"..\", default_sort = \"..\", key = \"..\", permissions(..) or hooks.",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The names of the derives on the struct, e.g. `GetAll` of `#[derive(api_derive::GetAll)]`.
+fn derived_names(attrs: &[syn::Attribute]) -> Vec<String> {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("derive"))
+        .filter_map(|a| a.parse_meta().ok())
+        .flat_map(nested_metas)
+        .filter_map(|meta| meta.path().segments.last().map(|segment| segment.ident.to_string()))
+        .collect()
+}
+
+#[proc_macro_derive(GetAll, attributes(author, api))]
 pub fn derive_get_all(tokens: TokenStream) -> TokenStream {
     let inputs = parse_macro_input![tokens as syn::ItemStruct];
+    if let Err(err) = check_struct_attrs(&inputs.attrs) {
+        return err.to_compile_error().into();
+    }
     let struct_ident = &inputs.ident;
-    let data_name = struct_ident.to_string().to_lowercase();
+    let names = ApiNames::new(struct_ident, &inputs.attrs);
+    let data_name = &names.data_name;
 
-    let author_field = &(*inputs
-        .fields
-        .iter()
-        .filter(|field| {
-            field.attrs.iter().any(|a| {
-                if let Ok(mt) = a.parse_meta() {
-                    return mt.path().is_ident("author");
-                }
-                false
-            })
-        })
-        .collect::<Vec<&syn::Field>>()
-        .first()
-        .expect("A member of the struct must have the #[author] attribute."))
-    .clone();
-
-    let author_type_name = format!("{}", author_field.ident.clone().unwrap());
-    let author_eq = format_ident!("{}_eq", author_type_name);
-    let url_path = format!("/{}", data_name);
+    let author_fields = match author_fields(&inputs) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let permission = match permission_check(&inputs.attrs, "List") {
+        Ok(check) => check,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let deleted_filter = match deleted_filter(&inputs.attrs, true) {
+        Ok(filter) => filter,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let url_path = format!("/{}", names.path);
     let fn_name = format_ident!("list_{}", data_name);
-    let coll_name = format!("{}s", data_name);
+    let coll_name = &names.coll_name;
+    let struct_name = struct_ident.to_string();
+
+    let field_names = field_names(&inputs);
+    let default_sort = match api_attr_str(&inputs.attrs, "default_sort") {
+        Some(lit) => {
+            let unknown = lit
+                .value()
+                .split(',')
+                .map(|field| field.trim().trim_start_matches(['-', '+']).to_owned())
+                .filter(|field| !field.is_empty() && !field_names.contains(field))
+                .collect::<Vec<String>>();
+            if !unknown.is_empty() {
+                let msg = format!("Unknown field in default_sort: {}", unknown.join(", "));
+                return syn::Error::new_spanned(lit, msg).to_compile_error().into();
+            }
+            lit.value()
+        }
+        None => String::new(),
+    };
+
+    let filter_arms = inputs.fields.iter().filter(|field| is_filterable(field)).map(|field| {
+        let field_name = serde_name(field);
+        match vec_item_type(&field.ty) {
+            Some(item_ty) => quote!(
+                #field_name => param.typed_value::<#item_ty>().map(|value| query.filter_any(#field_name, param.op, &value)),
+            ),
+            None => {
+                let field_ty = &field.ty;
+                quote!(
+                    #field_name => param.typed_value::<#field_ty>().map(|value| query.filter(#field_name, param.op, &value)),
+                )
+            }
+        }
+    });
+
+    let author_doc = author_fields
+        .iter()
+        .map(|author| format!(" for {} {{{}}}", author.name, author.param))
+        .collect::<String>();
+    let author_scope = author_filter(&author_fields);
+
+    // Author scoping and field filters, shared by the list and count handlers.
+    let scoped_query = quote!(
+        let filters = FilterParam::from_request(req).map_err(ApiError::BadRequest)?;
+        let coll = conn.context.collection_name(#coll_name);
+        let mut query = ListQuery::new(coll.as_str());
+        #author_scope
+        #deleted_filter
+        for param in filters {
+            query = match param.field.as_str() {
+                #(#filter_arms)*
+                _ => Err(format!("Unknown field {} of {}", param.field, #struct_name)),
+            }
+            .map_err(ApiError::BadRequest)?;
+        }
+    );
 
     let doc_comment = format!(
-        "/// List all documents ot type {} for author {}.
+        "/// List documents of type {}{}, one page at a time.
+        /// Paging is controlled by the `offset`, `limit` or `cursor` query parameters,
+        /// the response carries the `total` count and links to the `next` and `prev` pages.
+        /// The total is also sent in the `X-Total-Count` and `Content-Range` headers.
+        /// Any other query parameter filters on a field, e.g. `title=foo`, `number__gt=5` or `tags__in=a,b`,
+        /// the operators are eq, ne, gt, lt, ge, le, in and not_in.
+        /// `sort=-number,title` orders the documents, a `-` prefix means descending.
+        /// `fields=title,number` only returns the given fields of the documents, along with `_key`, `_id` and `_rev`.
         /// This handler can be mounted on {}.",
-        struct_ident, author_type_name, url_path
+        struct_ident, author_doc, url_path
+    );
+    let count_fn_name = format_ident!("count_{}", data_name);
+    let count_doc_comment = format!(
+        "/// Count documents of type {}{}, with the same filters as `{}`.
+        /// The count is returned as `{{\"total\": n}}` and in the `X-Total-Count` header.
+        /// This handler can be mounted on {}/count.",
+        struct_ident, author_doc, fn_name, url_path
     );
     let ts = quote!(
         #[doc = #doc_comment]
         pub async fn #fn_name(
             req: actix_web::HttpRequest,
             conn: actix_web::web::Data<arangoq::ArangoConnection>,
-        ) -> actix_web::HttpResponse {
-            log::debug!("{} entered", #coll_name);
-            use actix_web::{ FromRequest, HttpResponse };
-            use api_tools::web_query_ok;
-            use futures::future::Either;
-
-            let author = if let Ok(pth) = actix_web::web::Path::<String,>::extract(&req).await {
-                pth.into_inner().clone()
-            } else {
-                String::new()
-            };
-            log::debug!("{} for author: {}", #coll_name, author);
-            let coll = conn.context.collection_name(#coll_name);
-            let query =
-            if author.is_empty() {
-                #struct_ident::query_builder(coll.as_str())
-                    .read()
-                    .limit(100)
-                    .build()
-            } else {
-                #struct_ident::query_builder(coll.as_str())
-                    .read()
-                    .filter()
-                    .#author_eq(&author)
-                    .limit(100)
-                    .build()
-            };
-            log::debug!("{} db query: {:?}", #coll_name, query);
-            match web_query_ok::<#struct_ident>(query, &conn).await {
-                Either::Left(ar) => {
-                    if !ar.error {
-                        let coll_res = serde_json::json!({"collection" : &ar.result});
-                        HttpResponse::Ok().json(coll_res)
-                    } else {
-                        let msg = format!("Database Error:{} {}", ar.error_num, ar.error_message);
-                        log::error!("{:#?} -> {}", &ar, msg);
-                        HttpResponse::InternalServerError().json(Err::<(),_>(msg))
-                    }
-                },
-                Either::Right(err) => err,
+        ) -> Result<actix_web::HttpResponse, api_tools::ApiError> {
+            use actix_web::{ HttpRequest, HttpResponse };
+            use api_tools::{db_query_projected, fields_from_request, ApiError, FilterParam, ListQuery, Page, PageWindow, SortKey};
+
+            async fn handle(req: &HttpRequest, conn: &arangoq::ArangoConnection) -> Result<HttpResponse, ApiError> {
+                log::debug!("{} entered", #coll_name);
+                #permission
+                let window = PageWindow::from_request(req).map_err(ApiError::BadRequest)?;
+                let fields = fields_from_request(req, &[#(#field_names),*]).map_err(ApiError::BadRequest)?;
+                let sort = SortKey::from_request(req, &[#(#field_names),*], #default_sort)
+                    .map_err(ApiError::BadRequest)?;
+                #scoped_query
+                let query = query.sort(&sort).keep(&fields).build(window);
+                log::debug!("{} db query: {:?}", #coll_name, query);
+                let ar = db_query_projected::<#struct_ident>(query, conn, !fields.is_empty()).await?;
+                let total = ar.extra.stats.full_count;
+                let page = Page::new(req, window, ar.result, total);
+                Ok(HttpResponse::Ok()
+                    .header("X-Total-Count", total.to_string())
+                    .header("Content-Range", page.content_range())
+                    .json(page))
             }
+            handle(&req, &conn).await.map_err(|err| err.in_request(&req))
+        }
+
+        #[doc = #count_doc_comment]
+        pub async fn #count_fn_name(
+            req: actix_web::HttpRequest,
+            conn: actix_web::web::Data<arangoq::ArangoConnection>,
+        ) -> Result<actix_web::HttpResponse, api_tools::ApiError> {
+            use actix_web::{ HttpRequest, HttpResponse };
+            use api_tools::{db_query, ApiError, FilterParam, ListQuery};
+
+            async fn handle(req: &HttpRequest, conn: &arangoq::ArangoConnection) -> Result<HttpResponse, ApiError> {
+                log::debug!("{} count entered", #coll_name);
+                #permission
+                #scoped_query
+                let query = query.count();
+                log::debug!("{} db query: {:?}", #coll_name, query);
+                let ar = db_query::<usize>(query, conn).await?;
+                let total = ar.result.first().cloned().unwrap_or_default();
+                Ok(HttpResponse::Ok()
+                    .header("X-Total-Count", total.to_string())
+                    .json(serde_json::json!({ "total": total })))
+            }
+            handle(&req, &conn).await.map_err(|err| err.in_request(&req))
         }
     );
 
     ts.into()
 }
 
-#[proc_macro_derive(Fetch, attributes(author))]
+#[proc_macro_derive(Fetch, attributes(author, api))]
 pub fn derive_fetch(tokens: TokenStream) -> TokenStream {
     let inputs = parse_macro_input![tokens as syn::ItemStruct];
+    if let Err(err) = check_struct_attrs(&inputs.attrs) {
+        return err.to_compile_error().into();
+    }
     let struct_ident = &inputs.ident;
-    let data_name = struct_ident.to_string().to_lowercase();
+    let names = ApiNames::new(struct_ident, &inputs.attrs);
+    let data_name = &names.data_name;
 
-    let author_field = &(*inputs
-        .fields
-        .iter()
-        .filter(|field| {
-            field.attrs.iter().any(|a| {
-                if let Ok(mt) = a.parse_meta() {
-                    return mt.path().is_ident("author");
-                }
-                false
-            })
-        })
-        .collect::<Vec<&syn::Field>>()
-        .first()
-        .expect("A member of the struct must have the #[author] attribute."))
-    .clone();
-
-    let author_type_name = format!("{}", author_field.ident.clone().unwrap());
-    let author_eq = format_ident!("{}_eq", author_type_name);
-    let url_path = format!("/{}", data_name);
+    let author_fields = match author_fields(&inputs) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let permission = match permission_check(&inputs.attrs, "Fetch") {
+        Ok(check) => check,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let deleted_filter = match deleted_filter(&inputs.attrs, true) {
+        Ok(filter) => filter,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let author_filter = author_filter(&author_fields);
+    let url_path = format!("/{}", names.path);
     let fn_name = format_ident!("fetch_{}", data_name);
-    let coll_name = format!("{}s", data_name);
+    let coll_name = &names.coll_name;
+
+    let field_names = field_names(&inputs);
 
     let doc_comment = format!(
-        "/// Fetch a document of type {}.
+        "/// Fetch a document of type {}, responds 404 if there is none with the key.
+        /// `fields=title,number` only returns the given fields of the document, along with `_key`, `_id` and `_rev`.
+        /// The `ETag` is the `_rev` of the document, `If-None-Match` with the current one responds 304.
+        /// A projection on `fields` gets a tag of its own, see `api_tools::projected_rev`.
         /// This handler can be mounted on {}.",
         struct_ident, url_path
     );
@@ -202,180 +573,271 @@ pub fn derive_fetch(tokens: TokenStream) -> TokenStream {
         pub async fn #fn_name(
             req: actix_web::HttpRequest,
             conn: actix_web::web::Data<arangoq::ArangoConnection>,
-        ) -> actix_web::HttpResponse {
-            use actix_web::{ FromRequest, HttpResponse };
-            use api_tools::web_query_ok;
-            use futures::future::Either;
-
-            let r_key = req.match_info().get("key");
-            match r_key {
-                Some(key) => {
-                    let author = if let Ok(pth) = actix_web::web::Path::<(String,String)>::extract(&req).await {
-                        pth.0.clone()
-                    } else {
-                        String::new()
-                    };
+        ) -> Result<actix_web::HttpResponse, api_tools::ApiError> {
+            use actix_web::{ HttpRequest, HttpResponse };
+            use api_tools::{db_query_projected, etag, fields_from_request, if_none_match, projected_rev, rev_of, ApiError, ListQuery};
 
-                    let coll = conn.context.collection_name(#coll_name);
-                    let query = if author.is_empty() {
-                        let coll = Collection::new(coll.as_str(), CollectionType::Document);
-                        coll.get_by_key(key)
-                    } else {
-                        #struct_ident::query_builder(coll.as_str())
-                            .read()
-                            .filter()
-                            .#author_eq(&author)
-                            .filter()
-                            ._key_eq(&key.to_owned())
-                            .limit(1)
-                            .build()
-                    };
-                    log::debug!("{} db query: {:?}", #coll_name, query);
-                    match web_query_ok::<#struct_ident>(query, &conn).await {
-                        Either::Left(ar) => {
-                            if !ar.error {
-                                if let Some(data) = ar.result.first() {
-                                    HttpResponse::Ok().json(&data)
-                                } else {
-                                    HttpResponse::InternalServerError().json(Err::<(),_>("Empty db response."))
-                                }
-                            } else {
-                                let msg = format!("Database Error:{} {}", ar.error_num, ar.error_message);
-                                log::error!("{:#?} -> {}", &ar, msg);
-                                HttpResponse::NotFound().json(Err::<(),_>(msg))
-                            }
-                        },
-                        Either::Right(err) => err,
-                    }
-                },
-                None => {
-                    let path = req.path().to_string();
-                    let msg = format!("Can not fetch document without key on path: {}", path);
-                    HttpResponse::BadRequest().json(Err::<(),_>(msg))
+            async fn handle(req: &HttpRequest, conn: &arangoq::ArangoConnection) -> Result<HttpResponse, ApiError> {
+                #permission
+                let key = req.match_info().get("key").ok_or_else(|| {
+                    ApiError::BadRequest(format!("Can not fetch document without key on path: {}", req.path()))
+                })?;
+                let fields = fields_from_request(req, &[#(#field_names),*]).map_err(ApiError::BadRequest)?;
+
+                // DOCUMENT() would return null for a missing key, filtering returns nothing
+                let coll = conn.context.collection_name(#coll_name);
+                let mut query = ListQuery::new(coll.as_str()).filter_eq("_key", &key);
+                #author_filter
+                #deleted_filter
+                let query = query.keep(&fields).first();
+                log::debug!("{} db query: {:?}", #coll_name, query);
+                let ar = db_query_projected::<#struct_ident>(query, conn, !fields.is_empty()).await?;
+                let data = ar.result.first().ok_or_else(|| ApiError::not_found(#coll_name, key))?;
+                match rev_of(data).map(|rev| projected_rev(&rev, &fields)) {
+                    Some(rev) if if_none_match(req, &rev) => {
+                        Ok(HttpResponse::NotModified().header("ETag", etag(&rev)).finish())
+                    },
+                    Some(rev) => Ok(HttpResponse::Ok().header("ETag", etag(&rev)).json(data)),
+                    None => Ok(HttpResponse::Ok().json(data)),
                 }
             }
+            handle(&req, &conn).await.map_err(|err| err.in_request(&req))
         }
     );
 
     ts.into()
 }
 
-#[proc_macro_derive(Create, attributes(author))]
+#[proc_macro_derive(Create, attributes(author, api))]
 pub fn derive_create(tokens: TokenStream) -> TokenStream {
     let inputs = parse_macro_input![tokens as syn::ItemStruct];
+    if let Err(err) = check_struct_attrs(&inputs.attrs) {
+        return err.to_compile_error().into();
+    }
     let struct_ident = &inputs.ident;
-    let data_name = struct_ident.to_string().to_lowercase();
+    let names = ApiNames::new(struct_ident, &inputs.attrs);
+    let data_name = &names.data_name;
     let struct_name = struct_ident.to_string();
 
-    let author_field = &(*inputs
-        .fields
-        .iter()
-        .filter(|field| {
-            field.attrs.iter().any(|a| {
-                if let Ok(mt) = a.parse_meta() {
-                    return mt.path().is_ident("author");
-                }
-                false
-            })
-        })
-        .collect::<Vec<&syn::Field>>()
-        .first()
-        .expect("A member of the struct must have the #[author] attribute."))
-    .clone();
-    let author_prop = author_field.ident.clone().unwrap();
-    let author_type_name = format!("{}", author_field.ident.clone().unwrap());
-
-    let url_path = format!("/{}", data_name);
+    let author_fields = match author_fields(&inputs) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let permission = match permission_check(&inputs.attrs, "Create") {
+        Ok(check) => check,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let author_default = author_default(&author_fields, &struct_name);
+    let key_assignment = match key_assignment(&inputs, &struct_name) {
+        Ok(assignment) => assignment,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let hooks = api_flag(&inputs.attrs, "hooks");
+    let hook = |call: proc_macro2::TokenStream| if hooks { call } else { quote!() };
+    let before_create = hook(quote!(
+        api_tools::CrudHooks::before_create(&mut data, req).await?;
+    ));
+    let after_create = hook(quote!(api_tools::CrudHooks::after_create(&data, req).await?;));
+    let author_params = author_fields.iter().map(|author| &author.param);
+
+    let url_path = format!("/{}", names.path);
     let fn_name = format_ident!("create_{}", data_name);
-    let coll_name = format!("{}s", data_name);
+    let coll_name = &names.coll_name;
 
     let doc_comment = format!(
         "/// Create a new document of {} by posting to this handler.
+        /// The new document is published as `Event::Created` on the `EventBus` of the app.
+        /// Retries with the same `Idempotency-Key` header get the response of the first request,
+        /// if an `api_tools::IdempotencyConfig` is registered.
         /// This handler can be mounted on {}.",
         struct_name, url_path
     );
+    let bulk_fn_name = format_ident!("bulk_create_{}", data_name);
+    let bulk_doc_comment = format!(
+        "/// Create documents of {} by posting an array of them to this handler.
+        /// Each one is checked like by `{}`, the valid ones are inserted in one request, each on its own.
+        /// Responds 207 with the new document or the error of each, in the order of the request.
+        /// This handler can be mounted on {}/bulk.",
+        struct_name, fn_name, url_path
+    );
     let ts = quote!(
         #[doc = #doc_comment]
         pub async fn #fn_name(
-            data: actix_web::web::Json<#struct_ident>,
+            data: Result<actix_web::web::Json<#struct_ident>, actix_web::Error>,
             req: actix_web::HttpRequest,
             conn: actix_web::web::Data<arangoq::ArangoConnection>,
-            created_actor: actix_web::web::Data<actix::Addr<CreatedActor>>,
-        ) -> actix_web::HttpResponse {
-            log::debug!("{} entered", #coll_name);
-            use actix_web::{ FromRequest, HttpResponse };
+        ) -> Result<actix_web::HttpResponse, api_tools::ApiError> {
+            use actix_web::{ HttpRequest, HttpResponse };
             use validator::Validate;
-            use api_tools::web_query_ok;
-            use futures::future::Either;
+            use api_tools::{db_query, publish, ApiError, Created, Event};
+
+            // before the idempotency record is touched, a forbidden request gets no replay
+            fn authors(req: &HttpRequest) -> Result<Vec<Option<String>>, ApiError> {
+                #permission
+                Ok(vec![#(api_tools::resolve_author(req, #author_params)?),*])
+            }
 
-            match data.validate() {
-                Ok(_) => (),
-                Err(e) => {
-                    let msg = format!("Failure during validation of {}: {}", #struct_name, serde_json::to_string(&e).unwrap());
-                    log::warn!("{}", msg);
-                    return HttpResponse::NotFound().json(Err::<(),_>(msg));
+            async fn handle(
+                mut data: #struct_ident,
+                req: &HttpRequest,
+                conn: &arangoq::ArangoConnection,
+            ) -> Result<HttpResponse, ApiError> {
+                log::debug!("{} entered", #coll_name);
+                #author_default
+                #before_create
+                #key_assignment
+                data.validate().map_err(|e| ApiError::from_validation(#struct_name, &e))?;
+                api_tools::DbFields::stamp_created(&mut data, &api_tools::timestamp());
+
+                let coll = conn.context.collection_name(#coll_name);
+                let query = #struct_ident::query_builder(coll.as_str())
+                    .create(&data)
+                    .build();
+
+                log::debug!("{} db query: {:?}", #coll_name, query);
+                let ar = db_query::<#struct_ident>(query, conn).await?;
+                log::debug!("{} db response: {:#?}", #coll_name, &ar);
+                match ar.result.into_iter().next() {
+                    Some(data) => {
+                        // stored, so published even if the after hook fails
+                        publish(req, Event::Created(Created { data: data.clone() }));
+                        #after_create
+                        Ok(HttpResponse::Ok().json(&data))
+                    },
+                    None => Err(ApiError::Internal("Empty db response.".to_owned())),
                 }
-            };
-            let mut data = data.clone();
-
-            let author = if let Ok(pth) = actix_web::web::Path::<String,>::extract(&req).await {
-                let author = pth.into_inner();
-                if !data.#author_prop.eq(&author) {
-                    if !data.#author_prop.is_empty() {
-                        let msg = format!("Invalid {} of {}: {:#?}, should be: {}", #author_type_name, #struct_name, data.#author_prop, author);
-                        log::error!("{}", msg);
-                        return HttpResponse::BadRequest().json(Err::<(),_>(msg));
-                    } else {
-                        data.#author_prop = author.clone();
+            }
+            // retries of another caller don't get the stored response
+            let authors = authors(&req).map_err(|err| err.in_request(&req))?;
+            let data = ApiError::json_body(data).map_err(|err| err.in_request(&req))?;
+            let body = serde_json::to_value(&data).unwrap_or_default();
+            api_tools::idempotent(&req, &conn, &authors, &body, handle(data, &req, &conn))
+                .await
+                .map_err(|err| err.in_request(&req))
+        }
+
+        #[doc = #bulk_doc_comment]
+        pub async fn #bulk_fn_name(
+            input: Result<actix_web::web::Json<Vec<serde_json::Value>>, actix_web::Error>,
+            req: actix_web::HttpRequest,
+            conn: actix_web::web::Data<arangoq::ArangoConnection>,
+        ) -> Result<actix_web::HttpResponse, api_tools::ApiError> {
+            use actix_web::{ http::StatusCode, HttpRequest, HttpResponse };
+            use validator::Validate;
+            use api_tools::{bulk_write, check_bulk_size, publish, ApiError, BulkResponse, BulkWrite, Created, Event};
+
+            async fn handle(
+                input: Vec<serde_json::Value>,
+                req: &HttpRequest,
+                conn: &arangoq::ArangoConnection,
+            ) -> Result<HttpResponse, ApiError> {
+                log::debug!("{} bulk entered", #coll_name);
+                #permission
+                check_bulk_size(input.len())?;
+                let now = api_tools::timestamp();
+                let mut results = Vec::with_capacity(input.len());
+                for value in input {
+                    let result = async {
+                        let mut data = serde_json::from_value::<#struct_ident>(value)
+                            .map_err(|err| ApiError::BadRequest(format!("Bad data in request body: {}", err)))?;
+                        #author_default
+                        #before_create
+                        #key_assignment
+                        data.validate().map_err(|e| ApiError::from_validation(#struct_name, &e))?;
+                        api_tools::DbFields::stamp_created(&mut data, &now);
+                        Ok::<_, ApiError>(data)
                     }
+                    .await;
+                    results.push(result);
                 }
-                author
-            } else {
-                String::new()
-            };
 
-            let coll = conn.context.collection_name(#coll_name);
-            let query = #struct_ident::query_builder(coll.as_str())
-                .create(&data)
-                .build();
-
-            log::debug!("{} db query: {:?}", #coll_name, query);
-            match web_query_ok::<#struct_ident>(query, &conn).await {
-                Either::Left(ar) => {
-                    log::debug!("{} db response: {:#?}", #coll_name, &ar);
-                    if !ar.error {
-                        if let Some(data) = ar.result.first() {
-                            created_actor.do_send(Created{data: data.clone()});
-                            HttpResponse::Ok().json(&data)
-                        } else {
-                            HttpResponse::InternalServerError().json(Err::<(),_>("Empty db response."))
+                // the valid ones are inserted together, the db reports the error of each, e.g. a duplicate `_key`
+                let docs = results
+                    .iter()
+                    .filter_map(|result| result.as_ref().ok())
+                    .map(serde_json::to_value)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| ApiError::Internal(err.to_string()))?;
+                if !docs.is_empty() {
+                    let coll = conn.context.collection_name(#coll_name);
+                    let mut created = bulk_write::<#struct_ident>(conn, coll.as_str(), BulkWrite::Insert, &docs)
+                        .await?
+                        .into_iter();
+                    results = results
+                        .into_iter()
+                        .map(|result| {
+                            result.and_then(|_| {
+                                created.next().unwrap_or_else(|| Err(ApiError::Internal("Empty db response.".to_owned())))
+                            })
+                        })
+                        .collect();
+                }
+
+                let mut items = Vec::with_capacity(results.len());
+                for result in results {
+                    let item = match result {
+                        Ok(data) => async {
+                            publish(req, Event::Created(Created { data: data.clone() }));
+                            #after_create
+                            Ok(data)
                         }
-                    } else {
-                        let msg = format!("Database Error:{} {}", ar.error_num, ar.error_message);
-                        log::error!("{:#?} -> {}", &ar, msg);
-                        HttpResponse::InternalServerError().json(Err::<(),_>(msg))
-                    }
-                },
-                Either::Right(err) => err,
+                        .await,
+                        Err(err) => Err(err),
+                    };
+                    items.push(item);
+                }
+                Ok(BulkResponse::new(items, StatusCode::CREATED).respond())
             }
+            let input = ApiError::json_body(input).map_err(|err| err.in_request(&req))?;
+            handle(input, &req, &conn).await.map_err(|err| err.in_request(&req))
         }
     );
 
     ts.into()
 }
 
-#[proc_macro_derive(Update, attributes(author))]
+#[proc_macro_derive(Update, attributes(author, api))]
 pub fn derive_update(tokens: TokenStream) -> TokenStream {
     let inputs = parse_macro_input![tokens as syn::ItemStruct];
+    if let Err(err) = check_struct_attrs(&inputs.attrs) {
+        return err.to_compile_error().into();
+    }
     let struct_ident = &inputs.ident;
-    let data_name = struct_ident.to_string().to_lowercase();
+    let names = ApiNames::new(struct_ident, &inputs.attrs);
+    let data_name = &names.data_name;
+    let struct_name = struct_ident.to_string();
 
-    let url_path = format!("/{}/{{key}}", data_name);
+    let author_fields = match author_fields(&inputs) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let permission = match permission_check(&inputs.attrs, "Update") {
+        Ok(check) => check,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let deleted_filter = match deleted_filter(&inputs.attrs, false) {
+        Ok(filter) => filter,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let url_path = format!("/{}/{{key}}", names.path);
     let fn_name = format_ident!("update_{}", data_name);
-    let coll_name = format!("{}s", data_name);
+    let coll_name = &names.coll_name;
     let forbidden_char_msg =
         format!("Forbidden character found during validation of {}.", coll_name);
 
+    // The authors on the path scope the document, the patch can not move it to other authors.
+    let author_check = author_check(&author_fields, &struct_name);
+    let author_filter = author_filter(&author_fields);
+    let field_names = field_names(&inputs);
+    let hooks = api_flag(&inputs.attrs, "hooks");
+    let hook = |call: proc_macro2::TokenStream| if hooks { call } else { quote!() };
+    let before_update = hook(quote!(
+        api_tools::CrudHooks::before_update(&mut data, current, req).await?;
+    ));
+    let after_update =
+        hook(quote!(api_tools::CrudHooks::after_update(&data, current, req).await?;));
+
     let doc_comment = format!(
         "/// Update a document of {} by patch-ing to this handler.
         /// ```
@@ -387,312 +849,1131 @@ pub fn derive_update(tokens: TokenStream) -> TokenStream {
         /// // patch().body(\"{{ \"age\": 66 }}\")
         /// 
         /// ```
-        /// It may check _rev to deny accidentally changing a newer variant of the document.
+        /// The patch is merged into the current document, which is validated before it is stored.
+        /// Stored attributes which are no fields of {} are kept.
+        /// `Content-Type: application/json-patch+json` takes RFC 6902 operations instead,
+        /// `application/merge-patch+json` or `application/json` a RFC 7396 merge patch, others respond 415.
+        /// Both are published as `Event::Updated` on the `EventBus` of the app.
+        /// The `If-Match` header, or else the `_rev` of the patch, must be the current revision, or it responds 412.
         /// This handler can be mounted on {}.",
-        struct_ident, fn_name, url_path
+        struct_ident, fn_name, struct_ident, url_path
+    );
+    let bulk_fn_name = format_ident!("bulk_update_{}", data_name);
+    let bulk_doc_comment = format!(
+        "/// Update documents of {} by patch-ing an array of merge patches with their `_key` to this handler.
+        /// Each one is checked like by `{}`, the valid ones are replaced in one request, each on its own.
+        /// Responds 207 with the updated document or the error of each, in the order of the request.
+        /// This handler can be mounted on /{}/bulk.",
+        struct_ident, fn_name, names.path
     );
     let ts = quote!(
         #[doc = #doc_comment]
         // #[actix_web::patch(#url_path)]
         pub async fn #fn_name(
-            input: actix_web::web::Json<serde_json::Value>,
+            body: actix_web::web::Bytes,
             req: actix_web::HttpRequest,
             conn: actix_web::web::Data<arangoq::ArangoConnection>,
-        ) -> actix_web::HttpResponse {
-            log::debug!("{} entered", #coll_name);
-            use actix_web::{ FromRequest, HttpResponse };
+        ) -> Result<actix_web::HttpResponse, api_tools::ApiError> {
+            use actix_web::{ HttpRequest, HttpResponse };
             use validator::Validate;
-            use api_tools::{web_query_ok};
-            use futures::future::Either;
-
-            use json_patch::merge;
-
-            let r_key = req.match_info().get("key");
-            match r_key {
-                Some(key) => {
-                    let data_res = serde_json::to_string(&*input);
-                    log::debug!("Input({}): {:#?}", #coll_name, data_res);
-                    if data_res.is_err() {
-                        // this isn't supposed to happen, because we get a valid object only due to Json<UpdateParams> type
-                        let msg = format!("Bad data in request body: {}", data_res.unwrap_err());
-                        return HttpResponse::BadRequest().json(Err::<(),_>(msg));
-                    }
+            use api_tools::{db_query, etag, if_match_rev, publish, rev_of, ApiError, Event, ListQuery, PatchFormat, Updated};
 
-                    let valid = validator::validate_non_control_character(data_res.unwrap());
-                    if !valid {
-                        let msg = #forbidden_char_msg;
-                        log::warn!("{}", msg);
-                        return HttpResponse::BadRequest().json(Err::<(),_>(msg));
-                    }
+            async fn handle(
+                body: actix_web::web::Bytes,
+                req: &HttpRequest,
+                conn: &arangoq::ArangoConnection,
+            ) -> Result<HttpResponse, ApiError> {
+                log::debug!("{} entered", #coll_name);
+                #permission
+                let key = req
+                    .match_info()
+                    .get("key")
+                    .ok_or_else(|| ApiError::BadRequest("Can not update document without key.".to_owned()))?;
 
-                    let mut datamap = serde_json::to_value(#struct_ident::default()).unwrap();
-                    merge(&mut datamap, &input.0);
-                    log::debug!("{} -> {}", &input.0, datamap);
-                    if let Err(err) = serde_json::from_value::<#struct_ident>(datamap.clone()) {
-                        let msg = format!("{:?} line:{} column:{} classify?:{:?}, io?:{}, syntax?:{}, data?:{}, eof?:{}",
-                        err, err.line(), err.column(), err.classify(), err.is_io(), err.is_syntax(), err.is_data(), err.is_eof());
-                        log::error!("{} Error:{}", datamap, msg);
-                        return HttpResponse::BadRequest().json(Err::<(),_>(msg));
-                    }
+                let format = PatchFormat::from_request(req)?;
+                let data_str = std::str::from_utf8(&body)
+                    .map_err(|err| ApiError::BadRequest(format!("Bad data in request body: {}", err)))?;
+                log::debug!("Input({}): {:#?}", #coll_name, data_str);
 
-                    let coll = conn.context.collection_name(#coll_name);
-                    let collection = Collection::new(coll.as_str(), CollectionType::Document);
-                    let query = collection.update(key, &*input);
-                    log::debug!("update query: {:?}", query);
-                    match web_query_ok::<#struct_ident>(query, &conn).await {
-                        Either::Left(ar) => {
-                            if !ar.error {
-                                if let Some(data) = ar.result.first() {
-                                    HttpResponse::Ok().json(&data)
-                                } else {
-                                    HttpResponse::InternalServerError().json(Err::<(),_>("Empty db response."))
-                                }
-                            } else {
-                                let msg = format!("Database Error:{} {}", ar.error_num, ar.error_message);
-                                log::error!("{:#?} -> {}", &ar, msg);
-                                HttpResponse::InternalServerError().json(Err::<(),_>(msg))
-                            }
-                        },
-                        Either::Right(err) => err,
+                let valid = validator::validate_non_control_character(data_str);
+                if !valid {
+                    return Err(ApiError::BadRequest(#forbidden_char_msg.to_owned()));
+                }
+                let input: serde_json::Value = serde_json::from_str(data_str)
+                    .map_err(|err| ApiError::BadRequest(format!("Bad data in request body: {}", err)))?;
+
+                let coll = conn.context.collection_name(#coll_name);
+                let mut query = ListQuery::new(coll.as_str()).filter_eq("_key", &key);
+                #author_filter
+                #deleted_filter
+                let scoped = query;
+                let query = scoped.clone().first();
+                log::debug!("{} db query: {:?}", #coll_name, query);
+                let ar = db_query::<serde_json::Value>(query, conn).await?;
+                let stored = ar.result.into_iter().next().ok_or_else(|| ApiError::not_found(#coll_name, key))?;
+                let current = &serde_json::from_value::<#struct_ident>(stored.clone()).map_err(|err| {
+                    ApiError::Internal(format!("Stored {}/{} is no {}: {}", #coll_name, key, #struct_name, err))
+                })?;
+                let current_rev = rev_of(current);
+                let rev = if_match_rev(req).or_else(|| rev_of(&input)).or_else(|| current_rev.clone());
+                if current_rev.is_some() && rev != current_rev {
+                    let msg = format!("Revision {} of {}/{} is not the current one.", rev.unwrap_or_default(), #coll_name, key);
+                    return Err(ApiError::PreconditionFailed(msg));
+                }
+
+                // patched as json, the attributes the struct doesn't know are kept
+                let mut datamap = stored.clone();
+                format.apply(&mut datamap, &input)?;
+                log::debug!("{} -> {}", &input, datamap);
+                #author_check
+                let mut data = serde_json::from_value::<#struct_ident>(datamap.clone()).map_err(|err| {
+                    let msg = format!("{:?} line:{} column:{} classify?:{:?}, io?:{}, syntax?:{}, data?:{}, eof?:{}",
+                    err, err.line(), err.column(), err.classify(), err.is_io(), err.is_syntax(), err.is_data(), err.is_eof());
+                    log::error!("{} Error:{}", datamap, msg);
+                    ApiError::BadRequest(msg)
+                })?;
+                #before_update
+                data.validate().map_err(|e| ApiError::from_validation(#struct_name, &e))?;
+                api_tools::DbFields::stamp_updated(&mut data, &api_tools::timestamp());
+                let doc = serde_json::to_value(&data).map_err(|err| ApiError::Internal(err.to_string()))?;
+                let doc = api_tools::with_unknown_attributes(doc, &datamap, &[#(#field_names),*]);
+
+                // the db checks the revision again, in case it was changed since it was loaded
+                let query = scoped
+                    .preserve(<#struct_ident as api_tools::DbFields>::kept_fields())
+                    .replace(&doc, rev.as_deref());
+                log::debug!("update query: {:?}", query);
+                let ar = db_query::<#struct_ident>(query, conn).await?;
+                let data = ar.result.into_iter().next().ok_or_else(|| ApiError::not_found(#coll_name, key))?;
+                let mut response = HttpResponse::Ok();
+                if let Some(rev) = rev_of(&data) {
+                    response.header("ETag", etag(&rev));
+                }
+                // stored, so published even if the after hook fails
+                publish(req, Event::Updated(Updated { before: current.clone(), after: data.clone() }));
+                #after_update
+                Ok(response.json(&data))
+            }
+            handle(body, &req, &conn).await.map_err(|err| err.in_request(&req))
+        }
+
+        #[doc = #bulk_doc_comment]
+        pub async fn #bulk_fn_name(
+            input: Result<actix_web::web::Json<Vec<serde_json::Value>>, actix_web::Error>,
+            req: actix_web::HttpRequest,
+            conn: actix_web::web::Data<arangoq::ArangoConnection>,
+        ) -> Result<actix_web::HttpResponse, api_tools::ApiError> {
+            use actix_web::{ http::StatusCode, HttpRequest, HttpResponse };
+            use validator::Validate;
+            use api_tools::{bulk_write, check_bulk_size, db_query, key_of, publish, rev_of, ApiError, BulkResponse, BulkWrite, Event, FilterOp, ListQuery, PatchFormat, Updated};
+
+            async fn handle(
+                input: Vec<serde_json::Value>,
+                req: &HttpRequest,
+                conn: &arangoq::ArangoConnection,
+            ) -> Result<HttpResponse, ApiError> {
+                log::debug!("{} bulk entered", #coll_name);
+                #permission
+                check_bulk_size(input.len())?;
+                let keys = input
+                    .iter()
+                    .filter_map(|patch| patch.get("_key").and_then(|key| key.as_str()).map(ToOwned::to_owned))
+                    .collect::<Vec<String>>();
+
+                let coll = conn.context.collection_name(#coll_name);
+                let mut query = ListQuery::new(coll.as_str()).filter("_key", FilterOp::In, &keys);
+                #author_filter
+                #deleted_filter
+                let query = query.all().into_batched(keys.len().max(1));
+                log::debug!("{} db query: {:?}", #coll_name, query);
+                let ar = db_query::<serde_json::Value>(query, conn).await?;
+                let loaded = ar
+                    .result
+                    .into_iter()
+                    .filter_map(|doc| key_of(&doc).map(|key| (key, doc)))
+                    .collect::<std::collections::HashMap<String, serde_json::Value>>();
+
+                let now = api_tools::timestamp();
+                let mut results = Vec::with_capacity(input.len());
+                for input in input {
+                    let result = async {
+                        let key = input.get("_key").and_then(|key| key.as_str()).ok_or_else(|| {
+                            ApiError::BadRequest("Can not update document without _key.".to_owned())
+                        })?;
+                        let stored = loaded.get(key).ok_or_else(|| ApiError::not_found(#coll_name, key))?;
+                        let current = serde_json::from_value::<#struct_ident>(stored.clone()).map_err(|err| {
+                            ApiError::Internal(format!("Stored {}/{} is no {}: {}", #coll_name, key, #struct_name, err))
+                        })?;
+                        let current = &current;
+                        let current_rev = rev_of(current);
+                        let rev = rev_of(&input).or_else(|| current_rev.clone());
+                        if current_rev.is_some() && rev != current_rev {
+                            let msg = format!("Revision {} of {}/{} is not the current one.", rev.unwrap_or_default(), #coll_name, key);
+                            return Err(ApiError::PreconditionFailed(msg));
+                        }
+
+                        let mut datamap = stored.clone();
+                        PatchFormat::Merge.apply(&mut datamap, &input)?;
+                        #author_check
+                        let mut data = serde_json::from_value::<#struct_ident>(datamap.clone())
+                            .map_err(|err| ApiError::BadRequest(format!("Bad data in request body: {}", err)))?;
+                        #before_update
+                        data.validate().map_err(|e| ApiError::from_validation(#struct_name, &e))?;
+                        api_tools::DbFields::stamp_updated(&mut data, &now);
+                        let doc = serde_json::to_value(&data).map_err(|err| ApiError::Internal(err.to_string()))?;
+                        let mut doc = api_tools::with_unknown_attributes(doc, &datamap, &[#(#field_names),*]);
+                        api_tools::keep_stored(&mut doc, stored, <#struct_ident as api_tools::DbFields>::kept_fields());
+                        // the db rejects it, if it was changed since it was loaded
+                        api_tools::keep_stored(&mut doc, stored, &["_rev"]);
+                        Ok((current.clone(), doc))
                     }
-                },
-                None => HttpResponse::BadRequest()
-                .json(Err::<(),_>("Can not update document without key.")),
+                    .await;
+                    results.push(result);
+                }
+
+                // the valid ones are replaced together, the db reports the error of each
+                let docs = results
+                    .iter()
+                    .filter_map(|result| result.as_ref().ok().map(|(_, doc)| doc.clone()))
+                    .collect::<Vec<_>>();
+                let mut replaced = Vec::new().into_iter();
+                if !docs.is_empty() {
+                    replaced = bulk_write::<#struct_ident>(conn, coll.as_str(), BulkWrite::Replace, &docs)
+                        .await?
+                        .into_iter();
+                }
+                let results = results.into_iter().map(|result| {
+                    result.and_then(|(current, _)| {
+                        let data = replaced
+                            .next()
+                            .unwrap_or_else(|| Err(ApiError::Internal("Empty db response.".to_owned())))?;
+                        Ok((current, data))
+                    })
+                });
+
+                let mut items = Vec::with_capacity(results.len());
+                for result in results {
+                    let item = match result {
+                        Ok((current, data)) => async {
+                            let current = &current;
+                            publish(req, Event::Updated(Updated { before: current.clone(), after: data.clone() }));
+                            #after_update
+                            Ok(data)
+                        }
+                        .await,
+                        Err(err) => Err(err),
+                    };
+                    items.push(item);
+                }
+                Ok(BulkResponse::new(items, StatusCode::OK).respond())
             }
+            let input = ApiError::json_body(input).map_err(|err| err.in_request(&req))?;
+            handle(input, &req, &conn).await.map_err(|err| err.in_request(&req))
         }
     );
 
     ts.into()
 }
 
-#[proc_macro_derive(Replace, attributes(author))]
+#[proc_macro_derive(Replace, attributes(author, api))]
 pub fn derive_replace(tokens: TokenStream) -> TokenStream {
     let inputs = parse_macro_input![tokens as syn::ItemStruct];
+    if let Err(err) = check_struct_attrs(&inputs.attrs) {
+        return err.to_compile_error().into();
+    }
     let struct_ident = &inputs.ident;
-    let data_name = struct_ident.to_string().to_lowercase();
+    let names = ApiNames::new(struct_ident, &inputs.attrs);
+    let data_name = &names.data_name;
     let struct_name = struct_ident.to_string();
 
-    let author_field = &(*inputs
-        .fields
-        .iter()
-        .filter(|field| {
-            field.attrs.iter().any(|a| {
-                if let Ok(mt) = a.parse_meta() {
-                    return mt.path().is_ident("author");
-                }
-                false
-            })
-        })
-        .collect::<Vec<&syn::Field>>()
-        .first()
-        .expect("A member of the struct must have the #[author] attribute."))
-    .clone();
-    let author_prop = author_field.ident.clone().unwrap();
-    let author_type_name = format!("{}", author_field.ident.clone().unwrap());
-    let author_eq = format_ident!("{}_eq", author_type_name);
-
-    let url_path = format!("/{}", data_name);
+    let author_fields = match author_fields(&inputs) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let permission = match permission_check(&inputs.attrs, "Replace") {
+        Ok(check) => check,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let deleted_filter = match deleted_filter(&inputs.attrs, false) {
+        Ok(filter) => filter,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let author_default = author_default(&author_fields, &struct_name);
+    let author_filter = author_filter(&author_fields);
+    let hooks = api_flag(&inputs.attrs, "hooks");
+    let hook = |call: proc_macro2::TokenStream| if hooks { call } else { quote!() };
+    let before_replace = hook(quote!(
+        api_tools::CrudHooks::before_replace(&mut data, req).await?;
+    ));
+    let after_replace = hook(quote!(api_tools::CrudHooks::after_replace(&data, req).await?;));
+
+    let url_path = format!("/{}", names.path);
     let fn_name = format_ident!("replace_{}", data_name);
-    let coll_name = format!("{}s", data_name);
+    let coll_name = &names.coll_name;
 
     let doc_comment = format!(
         "/// Replace a new document of {} by posting to this handler.
-        /// It may check _rev to deny accidentally changing a newer variant of the document.
+        /// The new document is published as `Event::Replaced` on the `EventBus` of the app.
+        /// The `If-Match` header, or else the `_rev` of the document, must be the current revision, or it responds 412.
         /// This handler can be mounted on {}.",
         struct_name, url_path
     );
     let ts = quote!(
         #[doc = #doc_comment]
         pub async fn #fn_name(
-            data: actix_web::web::Json<#struct_ident>,
+            data: Result<actix_web::web::Json<#struct_ident>, actix_web::Error>,
             req: actix_web::HttpRequest,
             conn: actix_web::web::Data<arangoq::ArangoConnection>,
-        ) -> actix_web::HttpResponse {
-            log::debug!("{} entered", #coll_name);
-            use actix_web::{ FromRequest, HttpResponse };
+        ) -> Result<actix_web::HttpResponse, api_tools::ApiError> {
+            use actix_web::{ HttpRequest, HttpResponse };
             use validator::Validate;
-            use api_tools::web_query_ok;
-            use futures::future::Either;
-
-            let r_key = req.match_info().get("key");
-            match r_key {
-                Some(key) => {
-                    match data.validate() {
-                        Ok(_) => (),
-                        Err(e) => {
-                            let msg = format!("Failure during validation of {}: {}", #struct_name, serde_json::to_string(&e).unwrap());
-                            log::warn!("{}", msg);
-                            return HttpResponse::NotFound().json(Err::<(),_>(msg));
-                        }
-                    };
-                    let mut data = data.clone();
-
-                    let author = if let Ok(pth) = actix_web::web::Path::<(String,String)>::extract(&req).await {
-                        let author = pth.0.clone();
-                        if !data.#author_prop.eq(&author) {
-                            if !data.#author_prop.is_empty() {
-                                let msg = format!("Invalid {} of {}: {:#?}, should be: {}", #author_type_name, #struct_name, data.#author_prop, author);
-                                log::error!("{}", msg);
-                                return HttpResponse::BadRequest().json(Err::<(),_>(msg));
-                            } else {
-                                data.#author_prop = author.clone();
-                            }
-                        }
-                        author
-                    } else {
-                        String::new()
-                    };
+            use api_tools::{db_query, etag, if_match_rev, publish, rev_of, ApiError, Event, ListQuery, Replaced};
 
-                    let coll = conn.context.collection_name(#coll_name);
-                    let query = if author.is_empty() {
-                        #struct_ident::query_builder(coll.as_str())
-                            .update()
-                            .filter()
-                            ._key_eq(&key.to_owned())
-                            .replace_with(&data)
-                            .build()
-                    } else {
-                        #struct_ident::query_builder(coll.as_str())
-                            .update()
-                            .filter()
-                            .#author_eq(&author)
-                            .and()
-                            ._key_eq(&key.to_owned())
-                            .replace_with(&data)
-                            .build()
-                    };
+            async fn handle(
+                mut data: #struct_ident,
+                req: &HttpRequest,
+                conn: &arangoq::ArangoConnection,
+            ) -> Result<HttpResponse, ApiError> {
+                log::debug!("{} entered", #coll_name);
+                #permission
+                let key = req.match_info().get("key").ok_or_else(|| {
+                    ApiError::BadRequest(format!("Can not fetch document without key on path: {}", req.path()))
+                })?;
+                #author_default
+                #before_replace
+                data.validate().map_err(|e| ApiError::from_validation(#struct_name, &e))?;
+                api_tools::DbFields::stamp_updated(&mut data, &api_tools::timestamp());
 
-                    log::debug!("{} db query: {:?}", #coll_name, query);
-                    match web_query_ok::<#struct_ident>(query, &conn).await {
-                        Either::Left(ar) => {
-                            log::debug!("{} db response: {:#?}", #coll_name, &ar);
-                            if !ar.error {
-                                if let Some(data) = ar.result.first() {
-                                    // replaced_actor.do_send(Replaced{data: data.clone()});
-                                    HttpResponse::Ok().json(&data)
-                                } else {
-                                    HttpResponse::InternalServerError().json(Err::<(),_>("Empty db response."))
-                                }
-                            } else {
-                                let msg = format!("Database Error:{} {}", ar.error_num, ar.error_message);
-                                log::error!("{:#?} -> {}", &ar, msg);
-                                HttpResponse::InternalServerError().json(Err::<(),_>(msg))
-                            }
-                        },
-                        Either::Right(err) => err,
-                    }
-                },
-                None => {
-                    let path = req.path().to_string();
-                    let msg = format!("Can not fetch document without key on path: {}", path);
-                    HttpResponse::BadRequest().json(Err::<(),_>(msg))
+                let coll = conn.context.collection_name(#coll_name);
+                let mut query = ListQuery::new(coll.as_str()).filter_eq("_key", &key);
+                #author_filter
+                #deleted_filter
+                let rev = if_match_rev(req).or_else(|| rev_of(&data));
+                let query = query
+                    .preserve(<#struct_ident as api_tools::DbFields>::kept_fields())
+                    .replace(&data, rev.as_deref());
+
+                log::debug!("{} db query: {:?}", #coll_name, query);
+                let ar = db_query::<#struct_ident>(query, conn).await?;
+                log::debug!("{} db response: {:#?}", #coll_name, &ar);
+                let data = ar.result.into_iter().next().ok_or_else(|| ApiError::not_found(#coll_name, key))?;
+                let mut response = HttpResponse::Ok();
+                if let Some(rev) = rev_of(&data) {
+                    response.header("ETag", etag(&rev));
                 }
+                // stored, so published even if the after hook fails
+                publish(req, Event::Replaced(Replaced { data: data.clone() }));
+                #after_replace
+                Ok(response.json(&data))
             }
+            let data = ApiError::json_body(data).map_err(|err| err.in_request(&req))?;
+            handle(data, &req, &conn).await.map_err(|err| err.in_request(&req))
         }
     );
 
     ts.into()
 }
 
-#[proc_macro_derive(Delete, attributes(author))]
+#[proc_macro_derive(Delete, attributes(author, api))]
 pub fn derive_delete(tokens: TokenStream) -> TokenStream {
     let inputs = parse_macro_input![tokens as syn::ItemStruct];
+    if let Err(err) = check_struct_attrs(&inputs.attrs) {
+        return err.to_compile_error().into();
+    }
     let struct_ident = &inputs.ident;
-    let data_name = struct_ident.to_string().to_lowercase();
+    let names = ApiNames::new(struct_ident, &inputs.attrs);
+    let data_name = &names.data_name;
 
-    let author_field = &(*inputs
-        .fields
-        .iter()
-        .filter(|field| {
-            field.attrs.iter().any(|a| {
-                if let Ok(mt) = a.parse_meta() {
-                    return mt.path().is_ident("author");
-                }
-                false
-            })
-        })
-        .collect::<Vec<&syn::Field>>()
-        .first()
-        .expect("A member of the struct must have the #[author] attribute."))
-    .clone();
-
-    let author_type_name = format!("{}", author_field.ident.clone().unwrap());
-    let author_eq = format_ident!("{}_eq", author_type_name);
+    let author_fields = match author_fields(&inputs) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let permission = match permission_check(&inputs.attrs, "Delete") {
+        Ok(check) => check,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let restore_permission = match restore_permission(&inputs.attrs) {
+        Ok(check) => check,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let deleted_filter = match deleted_filter(&inputs.attrs, false) {
+        Ok(filter) => filter,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let soft_delete = api_flag(&inputs.attrs, "soft_delete");
+    let author_filter = author_filter(&author_fields);
+    let hooks = api_flag(&inputs.attrs, "hooks");
+    let hook = |call: proc_macro2::TokenStream| if hooks { call } else { quote!() };
+    let before_delete = hook(quote!(
+        <#struct_ident as api_tools::CrudHooks>::before_delete(key, req).await?;
+    ));
+    let after_delete = hook(quote!(api_tools::CrudHooks::after_delete(&data, req).await?;));
 
-    let url_path = format!("/{}/{{key}}", data_name);
+    let url_path = format!("/{}/{{key}}", names.path);
     let fn_name = format_ident!("delete_{}", data_name);
-    let coll_name = format!("{}s", data_name);
+    let coll_name = &names.coll_name;
 
+    let delete_query = if soft_delete {
+        quote!(query.update(
+            &api_tools::deleted_patch::<#struct_ident>(true, &api_tools::timestamp()),
+            if_match_rev(req).as_deref()
+        ))
+    } else {
+        quote!(query.remove(if_match_rev(req).as_deref()))
+    };
+    // the loaded `doc` at the revision it was loaded
+    let (bulk_write, bulk_delete_doc) = if soft_delete {
+        (
+            quote!(BulkWrite::Update),
+            quote!({
+                let mut patch = api_tools::deleted_patch::<#struct_ident>(true, &now);
+                patch["_key"] = serde_json::json!(key);
+                patch["_rev"] = doc["_rev"].clone();
+                patch
+            }),
+        )
+    } else {
+        (quote!(BulkWrite::Remove), quote!(serde_json::json!({ "_key": key, "_rev": doc["_rev"] })))
+    };
+    let bulk_delete_now =
+        if soft_delete { quote!(let now = api_tools::timestamp();) } else { quote!() };
+    let removal = if soft_delete {
+        format!("It is kept with a `deleted_at` and can be restored with `restore_{}`.", data_name)
+    } else {
+        "It is removed from the db.".to_owned()
+    };
     let doc_comment = format!(
-        "/// Delete a document of type {}.
-        /// It may check _rev to deny accidentally changing a newer variant of the document.
+        "/// Delete a document of type {}. {}
+        /// The deleted document is published as `Event::Deleted` on the `EventBus` of the app.
+        /// With an `If-Match` header it must be the current revision, or it responds 412.
         /// This handler can be mounted on {}.",
-        struct_ident, url_path
+        struct_ident, removal, url_path
     );
+    let bulk_fn_name = format_ident!("bulk_delete_{}", data_name);
+    let bulk_doc_comment = format!(
+        "/// Delete documents of type {} by sending an array of their keys to this handler.
+        /// Each one is deleted like by `{}`, in one request, each on its own at the revision it was loaded.
+        /// Responds 207 with the deleted document or the error of each, in the order of the request.
+        /// This handler can be mounted on /{}/bulk.",
+        struct_ident, fn_name, names.path
+    );
+    let restore_fn = if soft_delete {
+        let restore_fn_name = format_ident!("restore_{}", data_name);
+        let restore_doc_comment = format!(
+            "/// Restore a soft deleted document of type {}, the restored one is published as `Event::Restored`.
+            /// A document which isn't deleted responds 404, without a role to restore it's 403 for everyone.
+            /// With an `If-Match` header it must be the current revision, or it responds 412.
+            /// This handler can be mounted on {}/restore with POST.",
+            struct_ident, url_path
+        );
+        quote!(
+            #[doc = #restore_doc_comment]
+            pub async fn #restore_fn_name(
+                req: actix_web::HttpRequest,
+                conn: actix_web::web::Data<arangoq::ArangoConnection>,
+            ) -> Result<actix_web::HttpResponse, api_tools::ApiError> {
+                use actix_web::{ HttpRequest, HttpResponse };
+                use api_tools::{db_query, if_match_rev, publish, ApiError, Event, ListQuery, Restored};
+
+                async fn handle(req: &HttpRequest, conn: &arangoq::ArangoConnection) -> Result<HttpResponse, ApiError> {
+                    #restore_permission
+                    let key = req.match_info().get("key").ok_or_else(|| {
+                        ApiError::BadRequest(format!("Can not restore document without key on path: {}", req.path()))
+                    })?;
+
+                    let coll = conn.context.collection_name(#coll_name);
+                    let mut query = ListQuery::new(coll.as_str()).filter_eq("_key", &key);
+                    #author_filter
+                    let query = query
+                        .only_deleted()
+                        .update(
+                            &api_tools::deleted_patch::<#struct_ident>(false, &api_tools::timestamp()),
+                            if_match_rev(req).as_deref(),
+                        );
+                    log::debug!("{} restore query: {:?}", #coll_name, query);
+                    let ar = db_query::<#struct_ident>(query, conn).await?;
+                    match ar.result.into_iter().next() {
+                        Some(data) => {
+                            let response = HttpResponse::Ok().json(&data);
+                            publish(req, Event::Restored(Restored { data }));
+                            Ok(response)
+                        },
+                        None => Err(ApiError::not_found(#coll_name, key)),
+                    }
+                }
+                handle(&req, &conn).await.map_err(|err| err.in_request(&req))
+            }
+        )
+    } else {
+        quote!()
+    };
     let ts = quote!(
         #[doc = #doc_comment]
         // #[actix_web::get(#url_path)]
         pub async fn #fn_name(
             req: actix_web::HttpRequest,
             conn: actix_web::web::Data<arangoq::ArangoConnection>,
-        ) -> actix_web::HttpResponse {
-            use actix_web::{ FromRequest, HttpResponse };
-            use api_tools::web_query_ok;
-            use futures::future::Either;
-
-
-            let r_key = req.match_info().get("key");
-            match r_key {
-                Some(key) => {
-                    let author = if let Ok(pth) = actix_web::web::Path::<(String,String)>::extract(&req).await {
-                        pth.0.clone()
-                    } else {
-                        String::new()
-                    };
+        ) -> Result<actix_web::HttpResponse, api_tools::ApiError> {
+            use actix_web::{ HttpRequest, HttpResponse };
+            use api_tools::{db_query, if_match_rev, publish, ApiError, Deleted, Event, ListQuery};
+
+            async fn handle(req: &HttpRequest, conn: &arangoq::ArangoConnection) -> Result<HttpResponse, ApiError> {
+                #permission
+                let key = req.match_info().get("key").ok_or_else(|| {
+                    ApiError::BadRequest(format!("Can not fetch document without key on path: {}", req.path()))
+                })?;
+
+                let coll = conn.context.collection_name(#coll_name);
+                let mut query = ListQuery::new(coll.as_str()).filter_eq("_key", &key);
+                #author_filter
+                #deleted_filter
+                #before_delete
+                let query = #delete_query;
+                log::debug!("{} delete query: {:?}", #coll_name, query);
+                let ar = db_query::<#struct_ident>(query, conn).await?;
+                match ar.result.into_iter().next() {
+                    Some(data) => {
+                        // deleted, so published even if the after hook fails
+                        publish(req, Event::Deleted(Deleted { data: data.clone() }));
+                        #after_delete
+                        Ok(HttpResponse::Ok().json(&data))
+                    },
+                    None => Err(ApiError::not_found(#coll_name, key)),
+                }
+            }
+            handle(&req, &conn).await.map_err(|err| err.in_request(&req))
+        }
+
+        #[doc = #bulk_doc_comment]
+        pub async fn #bulk_fn_name(
+            input: Result<actix_web::web::Json<Vec<String>>, actix_web::Error>,
+            req: actix_web::HttpRequest,
+            conn: actix_web::web::Data<arangoq::ArangoConnection>,
+        ) -> Result<actix_web::HttpResponse, api_tools::ApiError> {
+            use actix_web::{ http::StatusCode, HttpRequest, HttpResponse };
+            use api_tools::{bulk_write, check_bulk_size, db_query, key_of, publish, ApiError, BulkResponse, BulkWrite, Deleted, Event, FilterOp, ListQuery};
+
+            async fn handle(
+                input: Vec<String>,
+                req: &HttpRequest,
+                conn: &arangoq::ArangoConnection,
+            ) -> Result<HttpResponse, ApiError> {
+                #permission
+                check_bulk_size(input.len())?;
+                let mut results = Vec::with_capacity(input.len());
+                for key in input {
+                    let result = async {
+                        let key = key.as_str();
+                        #before_delete
+                        Ok::<_, ApiError>(key.to_owned())
+                    }
+                    .await;
+                    results.push(result);
+                }
 
+                // the accepted ones in scope are deleted together, the db reports the error of each
+                let keys = results.iter().filter_map(|result| result.as_ref().ok()).collect::<Vec<_>>();
+                let mut deleted = std::collections::HashMap::new();
+                if !keys.is_empty() {
                     let coll = conn.context.collection_name(#coll_name);
-                    let query =
-                    if author.is_empty() {
-                        #struct_ident::query_builder(coll.as_str())
-                        .delete()
-                        .filter()
-                        ._key_eq(&key.to_owned())
-                        .build()
-                    } else {
-                        #struct_ident::query_builder(coll.as_str())
-                        .delete()
-                        .filter()
-                        .#author_eq(&author)
-                        .and()
-                        ._key_eq(&key.to_owned())
-                        .build()
-                    };
-                    log::debug!("{} delete query: {:?}", #coll_name, query);
-                    match web_query_ok::<#struct_ident>(query, &conn).await {
-                        Either::Left(ar) => {
-                            if !ar.error {
-                                if let Some(data) = ar.result.first() {
-                                    HttpResponse::Ok().json(&data)
-                                } else {
-                                    HttpResponse::InternalServerError().json(Err::<(),_>("Empty db response."))
-                                }
-                            } else {
-                                let msg = format!("Database Error:{} {}", ar.error_num, ar.error_message);
-                                log::error!("{:#?} -> {}", &ar, msg);
-                                HttpResponse::NotFound().json(Err::<(),_>(msg))
-                            }
-                        },
-                        Either::Right(err) => err,
+                    let mut query = ListQuery::new(coll.as_str()).filter("_key", FilterOp::In, &keys);
+                    #author_filter
+                    #deleted_filter
+                    let query = query.all().into_batched(keys.len());
+                    log::debug!("{} db query: {:?}", #coll_name, query);
+                    let ar = db_query::<serde_json::Value>(query, conn).await?;
+                    #bulk_delete_now
+                    let (loaded, docs): (Vec<String>, Vec<serde_json::Value>) = ar
+                        .result
+                        .iter()
+                        .filter_map(|doc| key_of(doc).map(|key| (key.clone(), #bulk_delete_doc)))
+                        .unzip();
+                    if !docs.is_empty() {
+                        let written = bulk_write::<#struct_ident>(conn, coll.as_str(), #bulk_write, &docs).await?;
+                        deleted = loaded.into_iter().zip(written).collect();
                     }
-                },
-                None => {
-                    let path = req.path().to_string();
-                    let msg = format!("Can not fetch document without key on path: {}", path);
-                    HttpResponse::BadRequest().json(Err::<(),_>(msg))
                 }
+
+                let mut items = Vec::with_capacity(results.len());
+                for result in results {
+                    let deleted = result.and_then(|key| {
+                        deleted.remove(&key).unwrap_or_else(|| Err(ApiError::not_found(#coll_name, &key)))
+                    });
+                    let item = match deleted {
+                        Ok(data) => async {
+                            publish(req, Event::Deleted(Deleted { data: data.clone() }));
+                            #after_delete
+                            Ok(data)
+                        }
+                        .await,
+                        Err(err) => Err(err),
+                    };
+                    items.push(item);
+                }
+                Ok(BulkResponse::new(items, StatusCode::OK).respond())
             }
+            let input = ApiError::json_body(input).map_err(|err| err.in_request(&req))?;
+            handle(input, &req, &conn).await.map_err(|err| err.in_request(&req))
         }
+
+        #restore_fn
     );
 
     ts.into()
 }
+
+#[proc_macro_derive(Upsert, attributes(author, api, upsert_key))]
+pub fn derive_upsert(tokens: TokenStream) -> TokenStream {
+    let inputs = parse_macro_input![tokens as syn::ItemStruct];
+    if let Err(err) = check_struct_attrs(&inputs.attrs) {
+        return err.to_compile_error().into();
+    }
+    let struct_ident = &inputs.ident;
+    let names = ApiNames::new(struct_ident, &inputs.attrs);
+    let data_name = &names.data_name;
+    let struct_name = struct_ident.to_string();
+
+    let author_fields = match author_fields(&inputs) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let key_fields = match upsert_key_fields(&inputs) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    // it creates or replaces, so it requires the roles of both
+    let permission = match (
+        permission_check(&inputs.attrs, "Create"),
+        permission_check(&inputs.attrs, "Replace"),
+    ) {
+        (Ok(create), Ok(replace)) => quote!(#create #replace),
+        (Err(err), _) | (_, Err(err)) => return err.to_compile_error().into(),
+    };
+    let author_default = author_default(&author_fields, &struct_name);
+    let key_assignment = match key_assignment(&inputs, &struct_name) {
+        Ok(assignment) => assignment,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let hooks = api_flag(&inputs.attrs, "hooks");
+    let hook = |call: proc_macro2::TokenStream| if hooks { call } else { quote!() };
+    let before_create = hook(quote!(
+        api_tools::CrudHooks::before_create(&mut data, req).await?;
+    ));
+    let after_create = hook(quote!(api_tools::CrudHooks::after_create(&data, req).await?;));
+    let before_replace = hook(quote!(
+        api_tools::CrudHooks::before_replace(&mut data, req).await?;
+    ));
+    let after_replace = hook(quote!(api_tools::CrudHooks::after_replace(&data, req).await?;));
+
+    // the documents of other authors are not found, the unique index rejects taking their key
+    let mut search_fields = key_fields.clone();
+    search_fields
+        .extend(author_fields.iter().map(|author| (author.ident.clone(), author.name.clone())));
+    let mut search = search_fields
+        .iter()
+        .map(
+            |(ident, name)| quote!((#name, serde_json::to_value(&data.#ident).unwrap_or_default())),
+        )
+        .collect::<Vec<_>>();
+    // nor are the soft deleted ones, they are restored by `restore_`, not by replacing them
+    let soft_delete = api_flag(&inputs.attrs, "soft_delete");
+    if soft_delete {
+        search.push(quote!(("deleted_at", serde_json::Value::Null)));
+    }
+    let key_doc = key_fields.iter().map(|(_, name)| name.as_str()).collect::<Vec<_>>().join(", ");
+    let deleted_doc = if soft_delete {
+        "\n        /// A soft deleted one isn't replaced, inserting another one conflicts with it, given the unique index."
+    } else {
+        ""
+    };
+
+    let url_path = format!("/{}", names.path);
+    let fn_name = format_ident!("upsert_{}", data_name);
+    let coll_name = &names.coll_name;
+
+    let doc_comment = format!(
+        "/// Create or replace a document of {} by putting it to this handler, it is found by {}.
+        /// Responds 201 and publishes `Event::Created` if there was none, else 200 and `Event::Replaced`.
+        /// It is looked up first, only the hooks of a create, with its `_key`, or those of a replace run.
+        /// The write is one `UPSERT` nonetheless, a document inserted or removed in the meantime gets that version.
+        /// A document of the same key inserted in the meantime responds 409, given a unique index on it.{}
+        /// This handler can be mounted on {} with PUT.",
+        struct_name, key_doc, deleted_doc, url_path
+    );
+    let ts = quote!(
+        #[doc = #doc_comment]
+        pub async fn #fn_name(
+            data: Result<actix_web::web::Json<#struct_ident>, actix_web::Error>,
+            req: actix_web::HttpRequest,
+            conn: actix_web::web::Data<arangoq::ArangoConnection>,
+        ) -> Result<actix_web::HttpResponse, api_tools::ApiError> {
+            use actix_web::{ HttpRequest, HttpResponse };
+            use validator::Validate;
+            use api_tools::{db_query, etag, publish, rev_of, ApiError, Created, Event, ListQuery, Replaced, Upserted};
+
+            async fn handle(
+                mut data: #struct_ident,
+                req: &HttpRequest,
+                conn: &arangoq::ArangoConnection,
+            ) -> Result<HttpResponse, ApiError> {
+                log::debug!("{} upsert entered", #coll_name);
+                #permission
+                #author_default
+                let search = [#(#search),*];
+
+                // whether there is one decides the hooks, those of a create or of a replace
+                let coll = conn.context.collection_name(#coll_name);
+                let mut query = ListQuery::new(coll.as_str());
+                for (field, value) in &search {
+                    query = query.filter_eq(field, value);
+                }
+                let query = query.first();
+                log::debug!("{} db query: {:?}", #coll_name, query);
+                let stored = db_query::<serde_json::Value>(query, conn).await?;
+                let now = api_tools::timestamp();
+                if stored.result.is_empty() {
+                    #before_create
+                    #key_assignment
+                    data.validate().map_err(|e| ApiError::from_validation(#struct_name, &e))?;
+                    api_tools::DbFields::stamp_created(&mut data, &now);
+                } else {
+                    #before_replace
+                    data.validate().map_err(|e| ApiError::from_validation(#struct_name, &e))?;
+                    api_tools::DbFields::stamp_updated(&mut data, &now);
+                }
+                let query = ListQuery::new(coll.as_str())
+                    .preserve(<#struct_ident as api_tools::DbFields>::kept_fields())
+                    .upsert(&search, &data, &data);
+
+                log::debug!("{} db query: {:?}", #coll_name, query);
+                let ar = db_query::<Upserted<#struct_ident>>(query, conn).await?;
+                log::debug!("{} db response: {:#?}", #coll_name, &ar);
+                let Upserted { doc: data, created } = ar
+                    .result
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| ApiError::Internal("Empty db response.".to_owned()))?;
+                let mut response = if created { HttpResponse::Created() } else { HttpResponse::Ok() };
+                if let Some(rev) = rev_of(&data) {
+                    response.header("ETag", etag(&rev));
+                }
+                // stored, so published even if the after hook fails
+                if created {
+                    publish(req, Event::Created(Created { data: data.clone() }));
+                    #after_create
+                } else {
+                    publish(req, Event::Replaced(Replaced { data: data.clone() }));
+                    #after_replace
+                }
+                Ok(response.json(&data))
+            }
+            let data = ApiError::json_body(data).map_err(|err| err.in_request(&req))?;
+            handle(data, &req, &conn).await.map_err(|err| err.in_request(&req))
+        }
+    );
+
+    ts.into()
+}
+
+/// Checks the role `#[api(permissions(list = "reader", delete = "admin"))]` requires for `operation`,
+/// with the `api_tools::PermissionChecker` of the app, nothing if it requires none.
+fn permission_check(
+    attrs: &[syn::Attribute],
+    operation: &str,
+) -> syn::Result<proc_macro2::TokenStream> {
+    const OPERATIONS: [&str; 7] =
+        ["List", "Fetch", "Create", "Update", "Replace", "Delete", "Restore"];
+    let mut role = None;
+    for meta in api_metas(attrs) {
+        let list = match meta {
+            syn::Meta::List(list) if list.path.is_ident("permissions") => list,
+            _ => continue,
+        };
+        for nested in list.nested {
+            let (op, lit) = match nested {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Str(lit),
+                    ..
+                })) => (path, lit),
+                other => {
+                    return Err(syn::Error::new_spanned(
+                        other,
+                        "Expected operation = \"role\", e.g. list = \"reader\".",
+                    ))
+                }
+            };
+            let op_name = op.get_ident().map(ToString::to_string).unwrap_or_default();
+            match OPERATIONS.iter().find(|known| known.to_lowercase() == op_name) {
+                Some(known) if *known == operation => role = Some(lit),
+                Some(_) => {}
+                None => {
+                    let msg = format!(
+                        "Unknown operation {}, expected one of list, fetch, create, update, replace, delete, restore.",
+                        op_name
+                    );
+                    return Err(syn::Error::new_spanned(op, msg));
+                }
+            }
+        }
+    }
+    let operation = format_ident!("{}", operation);
+    Ok(match role {
+        Some(role) => quote!(
+            api_tools::check_permission(req, api_tools::Operation::#operation, #role)?;
+        ),
+        None => quote!(),
+    })
+}
+
+/// With `#[derive_db_fields(SoftDelete)]` narrows `query` to the documents which aren't deleted,
+/// list and fetch also include them for `include_deleted=true` if the role to restore them is granted.
+fn deleted_filter(attrs: &[syn::Attribute], listed: bool) -> syn::Result<proc_macro2::TokenStream> {
+    if !api_flag(attrs, "soft_delete") {
+        return Ok(quote!());
+    }
+    if !listed {
+        return Ok(quote!(query = query.exclude_deleted();));
+    }
+    // the deleted documents are listed for those who may restore them
+    let permission = restore_permission(attrs)?;
+    Ok(quote!(
+        if api_tools::include_deleted(req).map_err(ApiError::BadRequest)? {
+            #permission
+        } else {
+            query = query.exclude_deleted();
+        }
+    ))
+}
+
+/// Checks the role to restore documents, which is also the one to list the deleted ones,
+/// nobody may without `#[api(permissions(restore = ".."))]`.
+fn restore_permission(attrs: &[syn::Attribute]) -> syn::Result<proc_macro2::TokenStream> {
+    let permission = permission_check(attrs, "Restore")?;
+    if !permission.is_empty() {
+        return Ok(permission);
+    }
+    // not a `return`, which would leave the rest of the handler unreachable
+    Ok(quote!(
+        let msg = "Restoring or listing deleted documents requires #[api(permissions(restore = \"..\"))].";
+        Err::<(), _>(ApiError::Forbidden(msg.to_owned()))?;
+    ))
+}
+
+/// A field with the `#[author]` attribute, the path parameter named `param` scopes the documents to its value,
+/// unless an `api_tools::AuthorResolver` decides otherwise.
+struct AuthorField {
+    ident: syn::Ident,
+    /// The name of the field in the document.
+    name: String,
+    /// `#[author(param = "uid")]`, the name of the field by default.
+    param: String,
+}
+
+/// The fields with an `#[author]` attribute, in the order of the struct.
+fn author_fields(inputs: &syn::ItemStruct) -> syn::Result<Vec<AuthorField>> {
+    let mut author_fields: Vec<AuthorField> = vec![];
+    for field in &inputs.fields {
+        for attr in field.attrs.iter().filter(|a| a.path.is_ident("author")) {
+            let ident = field.ident.clone().ok_or_else(|| {
+                syn::Error::new_spanned(attr, "#[author] requires a named field.")
+            })?;
+            let mut param = ident.to_string();
+            if !attr.tokens.is_empty() {
+                for meta in nested_metas(attr.parse_meta()?) {
+                    match meta {
+                        syn::Meta::NameValue(syn::MetaNameValue {
+                            ref path,
+                            lit: syn::Lit::Str(ref lit),
+                            ..
+                        }) if path.is_ident("param") => param = lit.value(),
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                meta,
+                                "Expected #[author] or #[author(param = \"name\")].",
+                            ))
+                        }
+                    }
+                }
+            }
+            if author_fields.iter().any(|author| author.param == param) {
+                let msg = format!("The path parameter {} scopes more than one field.", param);
+                return Err(syn::Error::new_spanned(attr, msg));
+            }
+            author_fields.push(AuthorField { ident, name: serde_name(field), param });
+        }
+    }
+    Ok(author_fields)
+}
+
+/// The identifiers and serialized names of the fields with `#[upsert_key]`, at least one is required.
+fn upsert_key_fields(inputs: &syn::ItemStruct) -> syn::Result<Vec<(syn::Ident, String)>> {
+    let mut key_fields = vec![];
+    for field in &inputs.fields {
+        for attr in field.attrs.iter().filter(|a| a.path.is_ident("upsert_key")) {
+            if !attr.tokens.is_empty() {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "Expected #[upsert_key] without arguments.",
+                ));
+            }
+            let ident = field.ident.clone().ok_or_else(|| {
+                syn::Error::new_spanned(attr, "#[upsert_key] requires a named field.")
+            })?;
+            key_fields.push((ident, serde_name(field)));
+        }
+    }
+    if key_fields.is_empty() {
+        let msg = "Upsert requires a field with #[upsert_key] to find the document by.";
+        return Err(syn::Error::new_spanned(&inputs.ident, msg));
+    }
+    Ok(key_fields)
+}
+
+/// Sets the `_key` of `data` as given by `#[api(key = "...")]`, without it ArangoDB generates the key.
+/// `uuid` and `ulid` generate one, `slug(field)` derives it from a field, `client` requires one in the body.
+fn key_assignment(
+    inputs: &syn::ItemStruct,
+    struct_name: &str,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let lit = match api_attr_str(&inputs.attrs, "key") {
+        Some(lit) => lit,
+        None => return Ok(quote!()),
+    };
+    let value = lit.value();
+    let assignment = match value.as_str() {
+        "uuid" => quote!(data._key = api_tools::uuid_key();),
+        "ulid" => quote!(data._key = api_tools::ulid_key();),
+        "client" => quote!(api_tools::require_key(#struct_name, &data._key)?;),
+        slug if slug.starts_with("slug(") && slug.ends_with(')') => {
+            let name = slug["slug(".len()..slug.len() - 1].trim();
+            let field = inputs
+                .fields
+                .iter()
+                .filter_map(|field| field.ident.as_ref())
+                .find(|ident| *ident == name)
+                .ok_or_else(|| {
+                    let msg = format!("Unknown field in key: {}", name);
+                    syn::Error::new_spanned(&lit, msg)
+                })?;
+            quote!(
+                data._key = api_tools::slug_key(&data.#field.to_string());
+                api_tools::require_key(#struct_name, &data._key)?;
+            )
+        }
+        _ => {
+            let msg =
+                format!("Unknown key: {}, expected uuid, ulid, slug(field) or client.", value);
+            return Err(syn::Error::new_spanned(&lit, msg));
+        }
+    };
+    Ok(assignment)
+}
+
+/// Narrows `query` to the documents of the authors on the path, unscoped routes don't have them.
+fn author_filter(author_fields: &[AuthorField]) -> proc_macro2::TokenStream {
+    let filters = author_fields.iter().map(|AuthorField { name, param, .. }| {
+        quote!(
+            if let Some(author) = api_tools::resolve_author(req, #param)? {
+                log::debug!("{} of {}", #name, author);
+                query = query.filter_eq(#name, &author);
+            }
+        )
+    });
+    quote!(#(#filters)*)
+}
+
+/// Sets the author fields of `data` to the authors on the path, or rejects different ones.
+fn author_default(author_fields: &[AuthorField], struct_name: &str) -> proc_macro2::TokenStream {
+    if author_fields.is_empty() {
+        return quote!();
+    }
+    let defaults = author_fields.iter().map(|AuthorField { ident, name, param }| {
+        quote!(
+            if let Some(author) = api_tools::resolve_author(req, #param)? {
+                if data.#ident != author {
+                    if !data.#ident.is_empty() {
+                        let msg = format!("Invalid {} of {}: {:#?}, should be: {}", #name, #struct_name, data.#ident, author);
+                        return Err(ApiError::BadRequest(msg));
+                    }
+                    data.#ident = author;
+                }
+            }
+        )
+    });
+    quote!(#(#defaults)*)
+}
+
+/// Rejects a patch which moved the document to other authors than those on the path.
+/// Compares the `stored` document with the patched `datamap`, both json.
+fn author_check(author_fields: &[AuthorField], struct_name: &str) -> proc_macro2::TokenStream {
+    if author_fields.is_empty() {
+        return quote!();
+    }
+    let checks = author_fields.iter().map(|AuthorField { name, param, .. }| {
+        quote!(
+            if let Some(author) = api_tools::resolve_author(req, #param)? {
+                let patched = datamap.get(#name).cloned().unwrap_or_default();
+                let changed = stored.get(#name) != Some(&patched);
+                if changed && patched.as_str() != Some(author.as_str()) {
+                    let msg = format!("Invalid {} of {}: {}, should be: {}", #name, #struct_name, patched, author);
+                    return Err(ApiError::BadRequest(msg));
+                }
+            }
+        )
+    });
+    quote!(#(#checks)*)
+}
+
+/// The serialized names of the fields which are attributes of the stored document.
+fn field_names(inputs: &syn::ItemStruct) -> Vec<String> {
+    inputs.fields.iter().filter(|field| is_filterable(field)).map(serde_name).collect()
+}
+
+/// The names of the handlers, the collection and the url segment of a struct,
+/// `#[api(collection = "categories", path = "blog-categories")]` overrides the last two.
+struct ApiNames {
+    /// `BlogPost` is `blogpost`, as in `list_blogpost`.
+    data_name: String,
+    /// `blog_posts` by default.
+    coll_name: String,
+    /// `blog-post` by default.
+    path: String,
+}
+
+impl ApiNames {
+    fn new(struct_ident: &syn::Ident, attrs: &[syn::Attribute]) -> Self {
+        let snake_name = snake_case(&struct_ident.to_string());
+        let coll_name = api_attr_str(attrs, "collection")
+            .map(|lit| lit.value())
+            .unwrap_or_else(|| pluralize(&snake_name));
+        let path = api_attr_str(attrs, "path")
+            .map(|lit| lit.value().trim_matches('/').to_owned())
+            .unwrap_or_else(|| snake_name.replace('_', "-"));
+        Self { data_name: struct_ident.to_string().to_lowercase(), coll_name, path }
+    }
+}
+
+/// `BlogPost` to `blog_post`, `HTTPLog` to `http_log`.
+fn snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower) {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+/// English plural of the last word, `category` to `categories`, `box` to `boxes`.
+fn pluralize(name: &str) -> String {
+    let before_y = name.chars().rev().nth(1);
+    if name.ends_with('y') && !before_y.is_some_and(|c| "aeiou".contains(c)) {
+        format!("{}ies", &name[..name.len() - 1])
+    } else if ["s", "x", "z", "ch", "sh"].iter().any(|end| name.ends_with(end)) {
+        format!("{}es", name)
+    } else {
+        format!("{}s", name)
+    }
+}
+
+/// True if the struct has a `#[api(name)]` attribute.
+fn api_flag(attrs: &[syn::Attribute], name: &str) -> bool {
+    api_metas(attrs).iter().any(|meta| match meta {
+        syn::Meta::Path(path) => path.is_ident(name),
+        _ => false,
+    })
+}
+
+/// The value of a `#[api(name = "value")]` attribute of the struct.
+fn api_attr_str(attrs: &[syn::Attribute], name: &str) -> Option<syn::LitStr> {
+    for meta in api_metas(attrs) {
+        if let syn::Meta::NameValue(nv) = meta {
+            if nv.path.is_ident(name) {
+                if let syn::Lit::Str(lit) = nv.lit {
+                    return Some(lit);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn api_metas(attrs: &[syn::Attribute]) -> Vec<syn::Meta> {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("api"))
+        .filter_map(|a| a.parse_meta().ok())
+        .flat_map(nested_metas)
+        .collect()
+}
+
+/// The name of the field in the serialized document, honouring `#[serde(rename = "...")]`.
+fn serde_name(field: &syn::Field) -> String {
+    for meta in serde_metas(field) {
+        if let syn::Meta::NameValue(nv) = meta {
+            if nv.path.is_ident("rename") {
+                if let syn::Lit::Str(lit) = nv.lit {
+                    return lit.value();
+                }
+            }
+        }
+    }
+    field.ident.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+/// Flattened and skipped fields are not attributes of the stored document.
+fn is_filterable(field: &syn::Field) -> bool {
+    !serde_metas(field).iter().any(|meta| {
+        if let syn::Meta::Path(path) = meta {
+            path.is_ident("flatten") || path.is_ident("skip") || path.is_ident("skip_deserializing")
+        } else {
+            false
+        }
+    })
+}
+
+fn serde_metas(field: &syn::Field) -> Vec<syn::Meta> {
+    field
+        .attrs
+        .iter()
+        .filter(|a| a.path.is_ident("serde"))
+        .filter_map(|a| a.parse_meta().ok())
+        .flat_map(nested_metas)
+        .collect()
+}
+
+fn nested_metas(meta: syn::Meta) -> Vec<syn::Meta> {
+    match meta {
+        syn::Meta::List(list) => list
+            .nested
+            .into_iter()
+            .filter_map(|nested| match nested {
+                syn::NestedMeta::Meta(meta) => Some(meta),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// The element type of a `Vec<T>` field.
+fn vec_item_type(ty: &syn::Type) -> Option<&syn::Type> {
+    if let syn::Type::Path(type_path) = ty {
+        let segment = type_path.path.segments.last()?;
+        if segment.ident == "Vec" {
+            if let syn::PathArguments::AngleBracketed(args) = &segment.arguments {
+                if let Some(syn::GenericArgument::Type(item_ty)) = args.args.first() {
+                    return Some(item_ty);
+                }
+            }
+        }
+    }
+    None
+}
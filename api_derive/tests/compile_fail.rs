@@ -0,0 +1,7 @@
+/// Misuse of the derives is reported as a compile error, see the `.stderr` next to each case.
+/// Regenerate those with `TRYBUILD=overwrite cargo test -p api_derive` after a message changed.
+#[test]
+fn test_compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
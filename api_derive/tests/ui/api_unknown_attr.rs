@@ -0,0 +1,15 @@
+#[macro_use]
+extern crate validator_derive;
+
+use api_derive::{derive_db_fields, Delete};
+use serde::{Deserialize, Serialize};
+
+// `permission` instead of `permissions` would delete without the check
+#[derive_db_fields(DropExtra)]
+#[derive(Serialize, Deserialize, Validate, Delete)]
+#[api(permission(delete = "admin"))]
+pub struct Post {
+    pub title: String,
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+#[macro_use]
+extern crate validator_derive;
+
+use api_derive::{derive_db_fields, Fetch};
+use serde::{Deserialize, Serialize};
+
+#[derive_db_fields(DropExtra)]
+#[derive(Serialize, Deserialize, Validate, Fetch)]
+pub struct Post {
+    #[author(name = "uid")]
+    pub author: String,
+    pub title: String,
+}
+
+fn main() {}
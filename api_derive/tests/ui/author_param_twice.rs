@@ -0,0 +1,16 @@
+#[macro_use]
+extern crate validator_derive;
+
+use api_derive::{derive_db_fields, Fetch};
+use serde::{Deserialize, Serialize};
+
+#[derive_db_fields(DropExtra)]
+#[derive(Serialize, Deserialize, Validate, Fetch)]
+pub struct Membership {
+    #[author(param = "uid")]
+    pub org: String,
+    #[author(param = "uid")]
+    pub user: String,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate validator_derive;
+
+use api_derive::{derive_db_fields, GetAll};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Validate, GetAll)]
+#[derive_db_fields(DropExtra)]
+pub struct Post {
+    pub title: String,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+#[macro_use]
+extern crate validator_derive;
+
+use api_derive::{derive_db_fields, Delete};
+use serde::{Deserialize, Serialize};
+
+// `SoftDeletes` instead of `SoftDelete` would remove the documents
+#[derive_db_fields(DropExtra, SoftDeletes)]
+#[derive(Serialize, Deserialize, Validate, Delete)]
+pub struct Post {
+    pub title: String,
+}
+
+fn main() {}
@@ -0,0 +1,159 @@
+use crate::ApiError;
+use actix_web::{http::header::HeaderName, web::Data, HttpRequest};
+use std::sync::Arc;
+
+/// Decides the author a request acts for, e.g. from a header, the claims of a JWT or a session.
+/// Without one the author on the path is trusted as is.
+/// ```ignore
+/// App::new().data(Authors::new(HeaderAuthor::new("X-User-Id")))
+/// ```
+pub trait AuthorResolver: Send + Sync {
+    /// The author of the documents for the scoping path parameter `param`,
+    /// `path_author` is its value on the path, if the route has one.
+    /// `None` leaves the documents unscoped, an error rejects the request, usually with 401 or 403.
+    fn resolve(
+        &self,
+        req: &HttpRequest,
+        param: &str,
+        path_author: Option<&str>,
+    ) -> Result<Option<String>, ApiError>;
+}
+
+/// The registered `AuthorResolver` of the app.
+#[derive(Clone)]
+pub struct Authors(Arc<dyn AuthorResolver>);
+
+impl Authors {
+    pub fn new<R: AuthorResolver + 'static>(resolver: R) -> Self {
+        Authors(Arc::new(resolver))
+    }
+}
+
+/// The author the derived handlers scope the documents to for the path parameter `param`.
+/// It is decided by the registered `Authors`, or else taken from the path.
+pub fn resolve_author(req: &HttpRequest, param: &str) -> Result<Option<String>, ApiError> {
+    let path_author = req
+        .match_info()
+        .get(param)
+        .filter(|author| !author.is_empty());
+    match req.app_data::<Data<Authors>>() {
+        Some(authors) => authors.0.resolve(req, param, path_author),
+        None => Ok(path_author.map(ToOwned::to_owned)),
+    }
+}
+
+/// Trusts a header set by an authenticating proxy, e.g. `X-User-Id`.
+/// Requests without it are rejected with 401, ones on the path of another author with 403.
+/// Unscoped routes are scoped to the author of the header.
+///
+/// It identifies a single author, by default for every scoping parameter.
+/// On a model with several `#[author]` fields, e.g. `org` and `user`, restrict it with `for_param`,
+/// the others are then taken from the path, or implement an `AuthorResolver` which tells them apart by `param`.
+pub struct HeaderAuthor {
+    header: HeaderName,
+    param: Option<String>,
+}
+
+impl HeaderAuthor {
+    /// Panics if `header` is not a valid header name.
+    pub fn new(header: &str) -> Self {
+        HeaderAuthor {
+            header: HeaderName::from_bytes(header.as_bytes()).expect("Invalid header name"),
+            param: None,
+        }
+    }
+
+    /// Only the author of the path parameter `param` is the one of the header.
+    pub fn for_param(mut self, param: &str) -> Self {
+        self.param = Some(param.to_owned());
+        self
+    }
+}
+
+impl AuthorResolver for HeaderAuthor {
+    fn resolve(
+        &self,
+        req: &HttpRequest,
+        param: &str,
+        path_author: Option<&str>,
+    ) -> Result<Option<String>, ApiError> {
+        if self.param.as_deref().is_some_and(|own| own != param) {
+            return Ok(path_author.map(ToOwned::to_owned));
+        }
+        let author = req
+            .headers()
+            .get(&self.header)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| ApiError::Unauthorized(format!("Missing {} header.", self.header)))?;
+        match path_author {
+            Some(path_author) if path_author != author => Err(ApiError::Forbidden(format!(
+                "{} {} can not act for {}.",
+                param, author, path_author
+            ))),
+            _ => Ok(Some(author.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_author, Authors, HeaderAuthor};
+    use crate::ApiError;
+    use actix_web::{test::TestRequest, web::Data};
+
+    #[test]
+    fn test_resolve_author() {
+        let req = TestRequest::with_uri("/4242/post")
+            .param("author", "4242")
+            .to_http_request();
+        assert_eq!(Ok(Some("4242".to_owned())), resolve_author(&req, "author"));
+        assert_eq!(Ok(None), resolve_author(&req, "uid"));
+
+        let authors = Data::new(Authors::new(HeaderAuthor::new("X-User-Id")));
+        let req = TestRequest::with_uri("/4242/post")
+            .param("author", "4242")
+            .app_data(authors.clone())
+            .to_http_request();
+        assert!(matches!(
+            resolve_author(&req, "author"),
+            Err(ApiError::Unauthorized(_))
+        ));
+
+        let req = TestRequest::with_uri("/4242/post")
+            .param("author", "4242")
+            .header("X-User-Id", "5500")
+            .app_data(authors.clone())
+            .to_http_request();
+        assert!(matches!(
+            resolve_author(&req, "author"),
+            Err(ApiError::Forbidden(_))
+        ));
+
+        let req = TestRequest::with_uri("/post")
+            .header("X-User-Id", "5500")
+            .app_data(authors)
+            .to_http_request();
+        assert_eq!(Ok(Some("5500".to_owned())), resolve_author(&req, "author"));
+
+        // the org of a membership is not the user of the header
+        let authors = Data::new(Authors::new(
+            HeaderAuthor::new("X-User-Id").for_param("uid"),
+        ));
+        let req = TestRequest::with_uri("/orgs/42/users/5500/membership")
+            .param("org", "42")
+            .param("uid", "5500")
+            .header("X-User-Id", "5500")
+            .app_data(authors.clone())
+            .to_http_request();
+        assert_eq!(Ok(Some("42".to_owned())), resolve_author(&req, "org"));
+        assert_eq!(Ok(Some("5500".to_owned())), resolve_author(&req, "uid"));
+
+        let req = TestRequest::with_uri("/membership")
+            .header("X-User-Id", "5500")
+            .app_data(authors)
+            .to_http_request();
+        assert_eq!(Ok(None), resolve_author(&req, "org"));
+        assert_eq!(Ok(Some("5500".to_owned())), resolve_author(&req, "uid"));
+    }
+}
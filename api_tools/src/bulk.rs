@@ -0,0 +1,268 @@
+use crate::{ApiError, ErrorBody};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use arangoq::ArangoConnection;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+/// Largest number of documents a bulk request may have.
+pub const MAX_BULK_SIZE: usize = 10_000;
+
+/// The outcome of one document of a bulk request, the `data` of a success or the `error`.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct BulkItem<T> {
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<ErrorBody>,
+}
+
+/// Response of the bulk handlers, sent as `207 Multi-Status`.
+/// `results` are in the order of the request.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct BulkResponse<T> {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BulkItem<T>>,
+}
+
+impl<T: Serialize> BulkResponse<T> {
+    /// Successes have the status `ok`, e.g. 201 for created documents.
+    pub fn new(results: Vec<Result<T, ApiError>>, ok: StatusCode) -> Self {
+        let results = results
+            .into_iter()
+            .map(|result| match result {
+                Ok(data) => BulkItem {
+                    status: ok.as_u16(),
+                    data: Some(data),
+                    error: None,
+                },
+                Err(err) => BulkItem {
+                    status: err.status_code().as_u16(),
+                    data: None,
+                    error: Some(err.body()),
+                },
+            })
+            .collect::<Vec<_>>();
+        let succeeded = results.iter().filter(|item| item.data.is_some()).count();
+        BulkResponse {
+            succeeded,
+            failed: results.len() - succeeded,
+            results,
+        }
+    }
+
+    pub fn respond(&self) -> HttpResponse {
+        HttpResponse::build(StatusCode::MULTI_STATUS).json(self)
+    }
+}
+
+/// The `_key` of a document, to match the results of a bulk query to the request.
+pub fn key_of<T: Serialize>(doc: &T) -> Option<String> {
+    serde_json::to_value(doc)
+        .ok()?
+        .get("_key")?
+        .as_str()
+        .map(ToOwned::to_owned)
+}
+
+/// Rejects empty and too large bulk requests.
+pub fn check_bulk_size(len: usize) -> Result<(), ApiError> {
+    if len == 0 || len > MAX_BULK_SIZE {
+        Err(ApiError::BadRequest(format!(
+            "A bulk request takes 1 to {} documents, not {}.",
+            MAX_BULK_SIZE, len
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// A write of many documents with the multi-document api of ArangoDB, `/_api/document/{collection}`.
+/// Unlike a query, which is all or nothing, the db writes each document on its own and reports its error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BulkWrite {
+    /// Inserts new documents, an existing `_key` is a conflict (1210).
+    Insert,
+    /// Replaces the documents of the same `_key`, their `_rev` must be the current one (1200).
+    Replace,
+    /// Merges into the documents of the same `_key`, a `null` removes the attribute.
+    Update,
+    /// Removes the documents of the same `_key`, their `_rev` must be the current one.
+    Remove,
+}
+
+impl BulkWrite {
+    fn params(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            BulkWrite::Insert => &[("returnNew", "true")],
+            BulkWrite::Replace => &[("returnNew", "true"), ("ignoreRevs", "false")],
+            BulkWrite::Update => &[
+                ("returnNew", "true"),
+                ("ignoreRevs", "false"),
+                ("keepNull", "false"),
+            ],
+            BulkWrite::Remove => &[("returnOld", "true"), ("ignoreRevs", "false")],
+        }
+    }
+}
+
+/// One element of the response of the multi-document api, or its error as a whole.
+#[derive(Debug, Default, Deserialize)]
+struct DocumentResult {
+    #[serde(default)]
+    error: bool,
+    #[serde(rename = "errorNum", default)]
+    error_num: u64,
+    #[serde(rename = "errorMessage", default)]
+    error_message: String,
+    new: Option<Value>,
+    old: Option<Value>,
+}
+
+impl DocumentResult {
+    fn into_doc<T: DeserializeOwned>(self, write: BulkWrite) -> Result<T, ApiError> {
+        if self.error {
+            return Err(ApiError::Db(
+                self.error_num,
+                format!("Database Error:{} {}", self.error_num, self.error_message),
+            ));
+        }
+        let doc = if write == BulkWrite::Remove {
+            self.old
+        } else {
+            self.new
+        };
+        let doc = doc.ok_or_else(|| ApiError::Internal("Empty db response.".to_owned()))?;
+        serde_json::from_value(doc).map_err(|err| ApiError::Internal(err.to_string()))
+    }
+}
+
+/// Writes the `docs` to the collection `coll` in one request, the result of each is in their order,
+/// the new document, or the removed one. Only a failure of the whole request is an error.
+pub async fn bulk_write<T: DeserializeOwned>(
+    conn: &ArangoConnection,
+    coll: &str,
+    write: BulkWrite,
+    docs: &[Value],
+) -> Result<Vec<Result<T, ApiError>>, ApiError> {
+    let url = format!("{}/_db/{}/_api/document/{}", conn.host, conn.database, coll);
+    let user = std::env::var("ARANGO_USER_NAME").unwrap_or_default();
+    let password = std::env::var("ARANGO_PASSWORD").ok();
+    let request = match write {
+        BulkWrite::Insert => conn.client.post(url.as_str()),
+        BulkWrite::Replace => conn.client.put(url.as_str()),
+        BulkWrite::Update => conn.client.patch(url.as_str()),
+        BulkWrite::Remove => conn.client.delete(url.as_str()),
+    };
+    let res = request
+        .query(write.params())
+        .basic_auth(&user, password.as_ref())
+        .json(docs)
+        .send()
+        .await
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    let body = res
+        .json::<Value>()
+        .await
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    log::debug!("{} bulk {:?} response: {}", coll, write, body);
+    match body {
+        Value::Array(results) if results.len() == docs.len() => Ok(results
+            .into_iter()
+            .map(|result| {
+                serde_json::from_value::<DocumentResult>(result)
+                    .map_err(|err| ApiError::Internal(err.to_string()))
+                    .and_then(|result| result.into_doc(write))
+            })
+            .collect()),
+        Value::Object(_) => {
+            let result = serde_json::from_value::<DocumentResult>(body).unwrap_or_default();
+            Err(result
+                .into_doc::<Value>(write)
+                .err()
+                .unwrap_or_else(|| ApiError::Internal("Unexpected db response.".to_owned())))
+        }
+        _ => Err(ApiError::Internal("Unexpected db response.".to_owned())),
+    }
+}
+
+/// Sets the `fields` of `doc` to those of the `stored` document, or removes them if it has none,
+/// e.g. the `kept_fields` of `DbFields`, which a request can't change.
+pub fn keep_stored(doc: &mut Value, stored: &Value, fields: &[&str]) {
+    if let Value::Object(doc) = doc {
+        for field in fields {
+            match stored.get(*field) {
+                Some(value) => doc.insert((*field).to_owned(), value.clone()),
+                None => doc.remove(*field),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_bulk_size, keep_stored, BulkResponse, BulkWrite, DocumentResult, MAX_BULK_SIZE,
+    };
+    use crate::ApiError;
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn test_bulk_response() {
+        let response = BulkResponse::new(
+            vec![Ok(1), Err(ApiError::not_found("posts", "42")), Ok(3)],
+            StatusCode::CREATED,
+        );
+        assert_eq!(
+            serde_json::json!({
+                "succeeded": 2,
+                "failed": 1,
+                "results": [
+                    { "status": 201, "data": 1 },
+                    {
+                        "status": 404,
+                        "error": { "code": 404, "message": "Document not found: posts/42", "errorNum": 1202 },
+                    },
+                    { "status": 201, "data": 3 },
+                ],
+            }),
+            serde_json::to_value(&response).unwrap()
+        );
+        assert_eq!(StatusCode::MULTI_STATUS, response.respond().status());
+
+        assert!(check_bulk_size(0).is_err());
+        assert!(check_bulk_size(MAX_BULK_SIZE).is_ok());
+        assert!(check_bulk_size(MAX_BULK_SIZE + 1).is_err());
+    }
+
+    #[test]
+    fn test_document_result() {
+        let result = serde_json::from_value::<DocumentResult>(serde_json::json!({
+            "_key": "a", "_rev": "_a", "new": { "_key": "a" }, "old": { "_key": "b" },
+        }))
+        .unwrap();
+        assert_eq!(
+            Ok(serde_json::json!({ "_key": "a" })),
+            result.into_doc::<serde_json::Value>(BulkWrite::Insert)
+        );
+
+        let result = serde_json::from_value::<DocumentResult>(serde_json::json!({
+            "error": true, "errorNum": 1210, "errorMessage": "unique constraint violated",
+        }))
+        .unwrap();
+        let err = result
+            .into_doc::<serde_json::Value>(BulkWrite::Insert)
+            .unwrap_err();
+        assert_eq!(409, err.body().code);
+
+        let mut doc =
+            serde_json::json!({ "title": "NU", "created_at": "now", "deleted_at": "now" });
+        let stored = serde_json::json!({ "title": "RU", "created_at": "then" });
+        keep_stored(&mut doc, &stored, &["created_at", "deleted_at"]);
+        assert_eq!(
+            serde_json::json!({ "title": "NU", "created_at": "then" }),
+            doc
+        );
+    }
+}
@@ -1,12 +1,53 @@
 use arangoq::arango_api::{GetByKey, GetByKeys, Update};
 use arangoq::{ArangoConnection, Collection, CollectionType};
 use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
 
 pub trait DbFields {
     fn _key(&self) -> String;
+
+    /// Sets the server side fields of a new document, e.g. `created_at` with `#[derive_db_fields(Timestamps)]`.
+    fn stamp_created(&mut self, _now: &str) {}
+
+    /// Sets the server side fields of a changed document, e.g. `updated_at`.
+    fn stamp_updated(&mut self, _now: &str) {}
+
+    /// The fields `stamp_updated` sets, as a patch for changes which don't replace the document, e.g. a soft delete.
+    fn updated_patch(_now: &str) -> Map<String, Value>
+    where
+        Self: Sized,
+    {
+        Map::new()
+    }
+
+    /// The fields of the stored document a replace keeps, e.g. `created_at`.
+    fn kept_fields() -> &'static [&'static str]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
+}
+
+/// The patch setting `deleted_at` of a `T`, to `now` to delete it or null to restore it,
+/// along with its `DbFields::updated_patch`, e.g. `updated_at`.
+pub fn deleted_patch<T: DbFields>(deleted: bool, now: &str) -> Value {
+    let mut patch = T::updated_patch(now);
+    let deleted_at = if deleted {
+        Value::from(now)
+    } else {
+        Value::Null
+    };
+    patch.insert("deleted_at".to_owned(), deleted_at);
+    Value::Object(patch)
 }
 
-///
+/// The current time as RFC 3339 in UTC, e.g. `2020-07-20T09:30:00.000Z`.
+pub fn timestamp() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// Load a single document by its `_key`.
 pub async fn load_by_key<Res>(
     key: String,
     conn: &ArangoConnection,
@@ -48,18 +89,12 @@ pub async fn update_in_db<R>(data: &R, conn: &ArangoConnection) -> Option<R>
 where
     R: std::fmt::Debug + DeserializeOwned + Serialize + DbFields + Clone,
 {
-    let local_name = super::get_type_name::<R>(&data).to_lowercase();
+    let local_name = super::get_type_name::<R>(data).to_lowercase();
     let db_coll = conn.context.collection_name(&local_name);
     let coll = Collection::new(db_coll.as_str(), CollectionType::Document);
     let query = coll.update(data._key(), data);
-    match query.try_exec::<R>(&conn).await {
-        Ok(ar) => {
-            if let Some(doc) = ar.result.first() {
-                Some(doc.clone())
-            } else {
-                None
-            }
-        }
+    match query.try_exec::<R>(conn).await {
+        Ok(ar) => ar.result.first().cloned(),
         Err(_) => None,
     }
 }
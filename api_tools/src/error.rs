@@ -0,0 +1,386 @@
+use actix_web::{http::StatusCode, web::Json, HttpRequest, HttpResponse, ResponseError};
+use arangoq::ArangoResponse;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+pub const ERROR_ARANGO_CONFLICT: u64 = 1200;
+pub const ERROR_ARANGO_DOCUMENT_NOT_FOUND: u64 = 1202;
+pub const ERROR_ARANGO_UNIQUE_CONSTRAINT_VIOLATED: u64 = 1210;
+
+/// The request id is taken from this header and sent back with errors.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+/// Errors are sent as RFC 7807 problem details, if the request accepts this.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Errors of the api handlers.
+/// Each one maps to a status code, the body is an `ErrorBody`, or a `Problem` if the client asked for one.
+/// ```ignore
+/// let window = PageWindow::from_request(&req).map_err(ApiError::BadRequest)?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    /// Malformed request, e.g. an unknown filter or a bad cursor.
+    BadRequest(String),
+    /// The document is invalid, with the violated rules of each field, see `ApiError::from_validation`.
+    Validation(String, FieldErrors),
+    /// The request has no, or no valid credentials.
+    Unauthorized(String),
+    /// The credentials don't allow the request, e.g. the author on the path is someone else.
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    PreconditionFailed(String),
+    /// The body is in a format the handler doesn't take, e.g. a patch which is neither a merge nor a json patch.
+    UnsupportedMediaType(String),
+    /// An error of ArangoDB with its error number, see `db_error_status`.
+    Db(u64, String),
+    Internal(String),
+    /// Any of the above, answering a request, see `ApiError::in_request`.
+    InRequest(Box<ApiError>, RequestInfo),
+}
+
+/// What an error response needs to know about the request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestInfo {
+    pub request_id: Option<String>,
+    pub problem_json: bool,
+}
+
+impl RequestInfo {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let headers = req.headers();
+        RequestInfo {
+            request_id: headers
+                .get(REQUEST_ID_HEADER)
+                .and_then(|id| id.to_str().ok())
+                .map(ToOwned::to_owned),
+            problem_json: headers
+                .get(actix_web::http::header::ACCEPT)
+                .and_then(|accept| accept.to_str().ok())
+                .is_some_and(|accept| accept.contains(PROBLEM_JSON)),
+        }
+    }
+}
+
+/// A rule violated by a field, e.g. `{"code": "length", "params": {"min": 2, "value": "a"}}`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FieldError {
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub params: BTreeMap<String, Value>,
+}
+
+/// Violated rules by field, nested fields are named like `address.city` or `items[0].name`.
+pub type FieldErrors = BTreeMap<String, Vec<FieldError>>;
+
+/// Body of the error responses.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ErrorBody {
+    pub code: u16,
+    pub message: String,
+    #[serde(rename = "errorNum", skip_serializing_if = "Option::is_none", default)]
+    pub error_num: Option<u64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub details: FieldErrors,
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none", default)]
+    pub request_id: Option<String>,
+}
+
+/// Body of the error responses as RFC 7807 problem details, with the same extension members as `ErrorBody`.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    #[serde(rename = "errorNum", skip_serializing_if = "Option::is_none", default)]
+    pub error_num: Option<u64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub details: FieldErrors,
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none", default)]
+    pub request_id: Option<String>,
+}
+
+/// The status code for an ArangoDB error number, unexpected errors are 500.
+pub fn db_error_status(error_num: u64) -> StatusCode {
+    match error_num {
+        ERROR_ARANGO_DOCUMENT_NOT_FOUND => StatusCode::NOT_FOUND,
+        ERROR_ARANGO_UNIQUE_CONSTRAINT_VIOLATED => StatusCode::CONFLICT,
+        ERROR_ARANGO_CONFLICT => StatusCode::PRECONDITION_FAILED,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+impl ApiError {
+    /// No document with the key in the collection, or not visible to the author.
+    pub fn not_found(collection: &str, key: &str) -> Self {
+        ApiError::NotFound(format!("Document not found: {}/{}", collection, key))
+    }
+
+    /// The error of a db response.
+    pub fn from_db<T>(ar: &ArangoResponse<T>) -> Self {
+        ApiError::Db(
+            ar.error_num,
+            format!("Database Error:{} {}", ar.error_num, ar.error_message),
+        )
+    }
+
+    /// The validation errors of a document of `struct_name`, with the violated rules of each field.
+    pub fn from_validation(struct_name: &str, errors: &ValidationErrors) -> Self {
+        let mut details = FieldErrors::new();
+        collect_field_errors("", errors, &mut details);
+        let fields = details.keys().cloned().collect::<Vec<String>>().join(", ");
+        let msg = format!("Failure during validation of {}: {}", struct_name, fields);
+        ApiError::Validation(msg, details)
+    }
+
+    /// The json body of a request, a malformed or mistyped one is a 400 like any other error of the handlers,
+    /// instead of the plain text response of actix. The `JsonConfig` of the app, e.g. its limit, still applies.
+    pub fn json_body<T>(body: Result<Json<T>, actix_web::Error>) -> Result<T, Self> {
+        body.map(Json::into_inner)
+            .map_err(|err| ApiError::BadRequest(format!("Bad data in request body: {}", err)))
+    }
+
+    /// Adds the request id and the preferred format of the request to the error.
+    pub fn in_request(self, req: &HttpRequest) -> Self {
+        match self {
+            ApiError::InRequest(..) => self,
+            err => ApiError::InRequest(Box::new(err), RequestInfo::from_request(req)),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::BadRequest(msg)
+            | ApiError::Validation(msg, _)
+            | ApiError::Unauthorized(msg)
+            | ApiError::Forbidden(msg)
+            | ApiError::NotFound(msg)
+            | ApiError::Conflict(msg)
+            | ApiError::PreconditionFailed(msg)
+            | ApiError::UnsupportedMediaType(msg)
+            | ApiError::Db(_, msg)
+            | ApiError::Internal(msg) => msg,
+            ApiError::InRequest(err, _) => err.message(),
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        let (error_num, details) = match self {
+            ApiError::Validation(_, details) => (None, details.clone()),
+            ApiError::NotFound(_) => (Some(ERROR_ARANGO_DOCUMENT_NOT_FOUND), FieldErrors::new()),
+            ApiError::Db(error_num, _) => (Some(*error_num), FieldErrors::new()),
+            ApiError::InRequest(err, info) => {
+                return ErrorBody {
+                    request_id: info.request_id.clone(),
+                    ..err.body()
+                }
+            }
+            _ => (None, FieldErrors::new()),
+        };
+        ErrorBody {
+            code: self.status_code().as_u16(),
+            message: self.message().to_owned(),
+            error_num,
+            details,
+            request_id: None,
+        }
+    }
+
+    pub fn problem(&self) -> Problem {
+        let status = self.status_code();
+        let body = self.body();
+        Problem {
+            problem_type: "about:blank".to_owned(),
+            title: status.canonical_reason().unwrap_or_default().to_owned(),
+            status: body.code,
+            detail: body.message,
+            error_num: body.error_num,
+            details: body.details,
+            request_id: body.request_id,
+        }
+    }
+}
+
+fn collect_field_errors(prefix: &str, errors: &ValidationErrors, details: &mut FieldErrors) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            (*field).to_owned()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                let field_errors = errors.iter().map(|err| FieldError {
+                    code: err.code.to_string(),
+                    message: err.message.as_ref().map(ToString::to_string),
+                    params: err
+                        .params
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), value.clone()))
+                        .collect(),
+                });
+                details.entry(path).or_default().extend(field_errors);
+            }
+            ValidationErrorsKind::Struct(errors) => collect_field_errors(&path, errors, details),
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    collect_field_errors(&format!("{}[{}]", path, index), errors, details);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Validation(..) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            ApiError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::Db(error_num, _) => db_error_status(*error_num),
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::InRequest(err, _) => err.status_code(),
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        if status.is_server_error() {
+            log::error!("{:?}", self);
+        } else {
+            log::warn!("{}", self);
+        }
+        let mut response = HttpResponse::build(status);
+        match self {
+            ApiError::InRequest(_, info) => {
+                if let Some(request_id) = &info.request_id {
+                    response.header(REQUEST_ID_HEADER, request_id.as_str());
+                }
+                if info.problem_json {
+                    return response.content_type(PROBLEM_JSON).json(self.problem());
+                }
+                response.json(self.body())
+            }
+            _ => response.json(self.body()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{db_error_status, ApiError, ErrorBody, FieldError, FieldErrors, RequestInfo};
+    use actix_web::http::StatusCode;
+    use actix_web::ResponseError;
+    use validator::{ValidationError, ValidationErrors};
+
+    #[test]
+    fn test_db_error_status() {
+        assert_eq!(StatusCode::NOT_FOUND, db_error_status(1202));
+        assert_eq!(StatusCode::CONFLICT, db_error_status(1210));
+        assert_eq!(StatusCode::PRECONDITION_FAILED, db_error_status(1200));
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, db_error_status(1203));
+    }
+
+    #[test]
+    fn test_error_body() {
+        let err = ApiError::not_found("posts", "42");
+        assert_eq!(StatusCode::NOT_FOUND, err.status_code());
+        assert_eq!(
+            serde_json::json!({
+                "code": 404,
+                "message": "Document not found: posts/42",
+                "errorNum": 1202,
+            }),
+            serde_json::to_value(err.body()).unwrap()
+        );
+
+        let mut details = FieldErrors::new();
+        details.insert(
+            "title".to_owned(),
+            vec![FieldError {
+                code: "length".to_owned(),
+                message: None,
+                params: Default::default(),
+            }],
+        );
+        let err = ApiError::InRequest(
+            Box::new(ApiError::Validation(
+                "Invalid Post".to_owned(),
+                details.clone(),
+            )),
+            RequestInfo {
+                request_id: Some("abc".to_owned()),
+                problem_json: true,
+            },
+        );
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, err.status_code());
+        assert_eq!(
+            ErrorBody {
+                code: 422,
+                message: "Invalid Post".to_owned(),
+                error_num: None,
+                details,
+                request_id: Some("abc".to_owned()),
+            },
+            err.body()
+        );
+        assert_eq!(
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Unprocessable Entity",
+                "status": 422,
+                "detail": "Invalid Post",
+                "details": { "title": [{ "code": "length" }] },
+                "requestId": "abc",
+            }),
+            serde_json::to_value(err.problem()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_from_validation() {
+        let mut length = ValidationError::new("length");
+        length.add_param("min".into(), &2);
+        length.add_param("value".into(), &"a");
+        let mut inner = ValidationErrors::new();
+        inner.add("city", ValidationError::new("required"));
+        let mut errors = ValidationErrors::new();
+        errors.add("title", length);
+        let errors = ValidationErrors::merge(Err(errors), "address", Err(inner)).unwrap_err();
+
+        let err = ApiError::from_validation("Post", &errors);
+        assert_eq!(
+            "Failure during validation of Post: address.city, title",
+            err.message()
+        );
+        assert_eq!(
+            serde_json::json!({
+                "code": 422,
+                "message": "Failure during validation of Post: address.city, title",
+                "details": {
+                    "address.city": [{ "code": "required" }],
+                    "title": [{ "code": "length", "params": { "min": 2, "value": "a" } }],
+                },
+            }),
+            serde_json::to_value(err.body()).unwrap()
+        );
+    }
+}
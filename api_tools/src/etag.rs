@@ -0,0 +1,100 @@
+use actix_web::http::header::{IF_MATCH, IF_NONE_MATCH};
+use actix_web::HttpRequest;
+use serde::Serialize;
+
+/// The `_rev` of a document, if it has one.
+pub fn rev_of<T: Serialize>(doc: &T) -> Option<String> {
+    serde_json::to_value(doc)
+        .ok()?
+        .get("_rev")?
+        .as_str()
+        .filter(|rev| !rev.is_empty())
+        .map(ToOwned::to_owned)
+}
+
+/// The `ETag` header value of a revision.
+pub fn etag(rev: &str) -> String {
+    format!("\"{}\"", rev)
+}
+
+/// The revision of the projection of a document on `fields`, for its `ETag`.
+/// Each projection gets a tag of its own, a cached part of the document doesn't answer a fetch of another part with 304.
+/// Fields are separated by `;`, commas separate the tags of a header.
+pub fn projected_rev(rev: &str, fields: &[String]) -> String {
+    if fields.is_empty() {
+        return rev.to_owned();
+    }
+    let mut fields = fields.to_vec();
+    fields.sort();
+    fields.dedup();
+    format!("{};{}", rev, fields.join(";"))
+}
+
+/// The revision required by the `If-Match` header, `*` matches any revision.
+pub fn if_match_rev(req: &HttpRequest) -> Option<String> {
+    header_revs(req, IF_MATCH)
+        .into_iter()
+        .find(|rev| rev != "*")
+}
+
+/// True if the `If-None-Match` header lists the revision, the client has it already.
+pub fn if_none_match(req: &HttpRequest, rev: &str) -> bool {
+    header_revs(req, IF_NONE_MATCH)
+        .iter()
+        .any(|tag| tag == "*" || tag == rev)
+}
+
+/// The entity tags of a header as revisions, weak tags are compared as strong ones.
+fn header_revs(req: &HttpRequest, name: actix_web::http::header::HeaderName) -> Vec<String> {
+    req.headers()
+        .get_all(name)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| {
+            tag.trim()
+                .trim_start_matches("W/")
+                .trim_matches('"')
+                .to_owned()
+        })
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{etag, if_match_rev, if_none_match, projected_rev, rev_of};
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_revs() {
+        assert_eq!(
+            Some("_abc".to_owned()),
+            rev_of(&serde_json::json!({ "_key": "42", "_rev": "_abc" }))
+        );
+        assert_eq!(
+            None,
+            rev_of(&serde_json::json!({ "_key": "42", "_rev": "" }))
+        );
+        assert_eq!("\"_abc\"", etag("_abc"));
+        assert_eq!("_abc", projected_rev("_abc", &[]));
+        let fields = ["title", "author", "title"].iter().map(|f| f.to_string());
+        assert_eq!(
+            "_abc;author;title",
+            projected_rev("_abc", &fields.collect::<Vec<_>>())
+        );
+
+        let req = TestRequest::default()
+            .header("If-Match", "\"_abc\"")
+            .header("If-None-Match", "W/\"_old\", \"_abc\"")
+            .to_http_request();
+        assert_eq!(Some("_abc".to_owned()), if_match_rev(&req));
+        assert!(if_none_match(&req, "_abc"));
+        assert!(!if_none_match(&req, "_new"));
+
+        let req = TestRequest::default()
+            .header("If-Match", "*")
+            .to_http_request();
+        assert_eq!(None, if_match_rev(&req));
+        assert!(!if_none_match(&req, "_abc"));
+    }
+}
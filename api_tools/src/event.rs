@@ -0,0 +1,133 @@
+use actix::{Message, Recipient};
+use actix_web::{web::Data, HttpRequest};
+use serde::{Deserialize, Serialize};
+
+/// A document was created.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Created<T> {
+    pub data: T,
+}
+
+/// A document was patched, `before` is the stored one, `after` the merged one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Updated<T> {
+    pub before: T,
+    pub after: T,
+}
+
+/// A document was replaced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replaced<T> {
+    pub data: T,
+}
+
+/// A document was deleted, `data` is the removed one, or the marked one with `#[derive_db_fields(SoftDelete)]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deleted<T> {
+    pub data: T,
+}
+
+/// A soft deleted document was restored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Restored<T> {
+    pub data: T,
+}
+
+/// The events the derived handlers publish after the db did the operation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event<T> {
+    Created(Created<T>),
+    Updated(Updated<T>),
+    Replaced(Replaced<T>),
+    Deleted(Deleted<T>),
+    Restored(Restored<T>),
+}
+
+impl<T: 'static> Message for Event<T> {
+    type Result = ();
+}
+
+/// Receives the events of documents of type `T`.
+pub trait EventListener<T> {
+    fn notify(&self, event: &Event<T>);
+}
+
+/// An actor handling `Event<T>` listens with its `recipient()`.
+impl<T: Clone + Send + 'static> EventListener<T> for Recipient<Event<T>> {
+    fn notify(&self, event: &Event<T>) {
+        if let Err(err) = self.do_send(event.clone()) {
+            log::error!("Event not delivered: {}", err);
+        }
+    }
+}
+
+/// The listeners of the events of documents of type `T`, register it as app data.
+/// Without one the handlers don't publish events.
+/// ```ignore
+/// App::new().data(EventBus::<Post>::new().subscribe(events_actor.recipient()))
+/// ```
+pub struct EventBus<T> {
+    listeners: Vec<Box<dyn EventListener<T>>>,
+}
+
+impl<T> Default for EventBus<T> {
+    fn default() -> Self {
+        EventBus { listeners: vec![] }
+    }
+}
+
+impl<T> EventBus<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe<L: EventListener<T> + 'static>(mut self, listener: L) -> Self {
+        self.listeners.push(Box::new(listener));
+        self
+    }
+
+    pub fn publish(&self, event: &Event<T>) {
+        for listener in &self.listeners {
+            listener.notify(event);
+        }
+    }
+}
+
+/// Publishes the event on the `EventBus<T>` of the app, if there is one.
+pub fn publish<T: 'static>(req: &HttpRequest, event: Event<T>) {
+    if let Some(bus) = req.app_data::<Data<EventBus<T>>>() {
+        bus.publish(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{publish, Created, Deleted, Event, EventBus, EventListener};
+    use actix_web::{test::TestRequest, web::Data};
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl EventListener<u8> for Recorder {
+        fn notify(&self, event: &Event<u8>) {
+            let json = serde_json::to_string(event).unwrap();
+            self.0.lock().unwrap().push(json);
+        }
+    }
+
+    #[test]
+    fn test_publish() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let bus = Data::new(EventBus::new().subscribe(Recorder(events.clone())));
+        let req = TestRequest::default().app_data(bus).to_http_request();
+        publish(&req, Event::Created(Created { data: 1u8 }));
+        // only the bus of the document type gets it
+        publish(&req, Event::Deleted(Deleted { data: 2u16 }));
+
+        assert_eq!(
+            vec![r#"{"event":"created","data":1}"#.to_owned()],
+            *events.lock().unwrap()
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use crate::ApiError;
+use actix_web::HttpRequest;
+pub use async_trait::async_trait;
+
+/// Hooks of a model the derived handlers call with `#[api(hooks)]` on the struct.
+/// The `before_` hooks can change the document, e.g. set a timestamp or a slug, it is validated after them.
+/// An error of a `before_` hook aborts the operation, one of an `after_` hook is the response,
+/// though the db did the operation already and its `Event` is published before the hook runs.
+/// ```ignore
+/// #[async_trait(?Send)]
+/// impl CrudHooks for Post {
+///     async fn before_create(&mut self, _req: &HttpRequest) -> Result<(), ApiError> {
+///         self.slug = slugify(&self.title);
+///         Ok(())
+///     }
+/// }
+/// ```
+#[async_trait(?Send)]
+pub trait CrudHooks: Sized {
+    async fn before_create(&mut self, _req: &HttpRequest) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    async fn after_create(&self, _req: &HttpRequest) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    /// `self` is the patched document, `before` the stored one.
+    async fn before_update(&mut self, _before: &Self, _req: &HttpRequest) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    async fn after_update(&self, _before: &Self, _req: &HttpRequest) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    async fn before_replace(&mut self, _req: &HttpRequest) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    async fn after_replace(&self, _req: &HttpRequest) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    /// The document is not loaded before it is deleted, only its `key` is known.
+    async fn before_delete(_key: &str, _req: &HttpRequest) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    /// `self` is the deleted document.
+    async fn after_delete(&self, _req: &HttpRequest) -> Result<(), ApiError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{db_query, timestamp, ApiError, FieldErrors};
+use actix_web::dev::{Body, ResponseBody};
+use actix_web::http::{header::AUTHORIZATION, StatusCode};
+use actix_web::{web::Data, HttpRequest, HttpResponse};
+use arangoq::{ArangoConnection, ArangoQuery};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::future::Future;
+
+/// Retries of a request send the same key in this header.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// Set on a response which was stored for an earlier request with the same key.
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// Where and how long the responses to requests with an `Idempotency-Key` are kept.
+/// Register it as app data to honour the header, the collection is created by `setup`:
+/// ```ignore
+/// let idempotency = IdempotencyConfig::default();
+/// idempotency.setup(&conn).await?;
+/// App::new().data(idempotency)
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdempotencyConfig {
+    /// Local name of the collection, prefixed like the others.
+    pub collection: String,
+    /// Seconds the response to the first request is kept.
+    pub ttl: i64,
+    /// Seconds a key is reserved while the first request is handled,
+    /// a retry after a crashed or hanging one runs it again once they are over.
+    pub lock_ttl: i64,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        IdempotencyConfig {
+            collection: "idempotency_keys".to_owned(),
+            ttl: 24 * 60 * 60,
+            lock_ttl: 60,
+        }
+    }
+}
+
+impl IdempotencyConfig {
+    /// Creates the collection with a ttl index, the db removes the expired keys.
+    /// An existing collection or index is kept.
+    pub async fn setup(&self, conn: &ArangoConnection) -> Result<(), String> {
+        let coll = conn.context.collection_name(&self.collection);
+        let user = std::env::var("ARANGO_USER_NAME").unwrap_or_default();
+        let password = std::env::var("ARANGO_PASSWORD").ok();
+
+        let collection = serde_json::json!({ "name": coll, "type": 2 });
+        let res = conn
+            .client
+            .post(conn.collection().as_str())
+            .basic_auth(&user, password.as_ref())
+            .json(&collection)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        log::debug!("{} collection: {}", coll, res.status());
+
+        let index =
+            serde_json::json!({ "type": "ttl", "fields": ["expires_at"], "expireAfter": 0 });
+        let res = conn
+            .client
+            .post(format!("{}/_db/{}/_api/index", conn.host, conn.database).as_str())
+            .query(&[("collection", coll.as_str())])
+            .basic_auth(&user, password.as_ref())
+            .json(&index)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if res.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Can not create the ttl index of {}: {}",
+                coll,
+                res.status()
+            ))
+        }
+    }
+}
+
+/// A request with an `Idempotency-Key`, and its response once there is one.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdempotencyRecord {
+    pub _key: String,
+    /// Of the body of the request, see `fingerprint`.
+    pub fingerprint: String,
+    /// RFC 3339, the ttl index removes it after this.
+    pub expires_at: String,
+    /// 0 while the first request is handled.
+    #[serde(default)]
+    pub status: u16,
+    #[serde(default)]
+    pub body: Value,
+}
+
+/// The stored record of a key, `reserved` if it is the new one of this request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reservation {
+    pub record: IdempotencyRecord,
+    pub reserved: bool,
+}
+
+/// Identifies the body of a request, retries must send the same one.
+pub fn fingerprint(body: &Value) -> String {
+    let json = serde_json::to_vec(body).unwrap_or_default();
+    sha1::Sha1::from(json).digest().to_string()
+}
+
+/// The key of the record of `key`, scoped to the route and the caller, so another caller sending the same key
+/// doesn't get the response of the first one. The caller is identified by the resolved `authors`
+/// and the `Authorization` header. Keys can be anything, so they are hashed.
+fn scoped_key(req: &HttpRequest, authors: &[Option<String>], key: &str) -> String {
+    let authorization = req
+        .headers()
+        .get(AUTHORIZATION)
+        .map(|value| value.as_bytes());
+    let scoped_key = format!(
+        "{} {} {:?} {:?} {}",
+        req.method(),
+        req.path(),
+        authors,
+        authorization,
+        key
+    );
+    sha1::Sha1::from(scoped_key).digest().to_string()
+}
+
+/// RFC 3339 time `seconds` after `now`, for `expires_at`.
+fn expires_at(now: chrono::DateTime<chrono::Utc>, seconds: i64) -> String {
+    (now + chrono::Duration::seconds(seconds)).to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// Runs `handle` once for each `Idempotency-Key`, retries get the stored response of the first request.
+/// Without the header, or a registered `IdempotencyConfig`, it simply runs `handle`.
+/// The same key with another body is rejected with 422, while the first request is handled with 409.
+/// A failed request releases the key, so it can be retried.
+/// `authors` are those the request acts for, see `scoped_key`.
+pub async fn idempotent<F>(
+    req: &HttpRequest,
+    conn: &ArangoConnection,
+    authors: &[Option<String>],
+    body: &Value,
+    handle: F,
+) -> Result<HttpResponse, ApiError>
+where
+    F: Future<Output = Result<HttpResponse, ApiError>>,
+{
+    let key = req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|key| key.to_str().ok())
+        .filter(|key| !key.is_empty());
+    let (config, key) = match (req.app_data::<Data<IdempotencyConfig>>(), key) {
+        (Some(config), Some(key)) => (config, key),
+        _ => return handle.await,
+    };
+    let coll = conn.context.collection_name(&config.collection);
+    // the key is only leased until the response is stored, an expired lease is free again
+    let now = chrono::Utc::now();
+    let record = IdempotencyRecord {
+        _key: scoped_key(req, authors, key),
+        fingerprint: fingerprint(body),
+        expires_at: expires_at(now, config.lock_ttl),
+        status: 0,
+        body: Value::Null,
+    };
+
+    let reservation = reserve(&coll, &record, &timestamp(), conn).await?;
+    if !reservation.reserved {
+        let stored = reservation.record;
+        return if stored.fingerprint != record.fingerprint {
+            let msg = format!(
+                "{} {} was used with another request.",
+                IDEMPOTENCY_KEY_HEADER, key
+            );
+            Err(ApiError::Validation(msg, FieldErrors::new()))
+        } else if stored.status == 0 {
+            let msg = format!(
+                "The request with {} {} is in progress.",
+                IDEMPOTENCY_KEY_HEADER, key
+            );
+            Err(ApiError::Conflict(msg))
+        } else {
+            let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+            Ok(HttpResponse::build(status)
+                .header(IDEMPOTENT_REPLAYED_HEADER, "true")
+                .json(stored.body))
+        };
+    }
+
+    let result = handle.await;
+    let mut bind_vars = BTreeMap::new();
+    bind_vars.insert("@collection".to_owned(), Value::String(coll));
+    bind_vars.insert("key".to_owned(), Value::String(record._key));
+    let query = match &result {
+        Ok(response) => {
+            bind_vars.insert("status".to_owned(), Value::from(response.status().as_u16()));
+            bind_vars.insert("body".to_owned(), response_json(response));
+            bind_vars.insert(
+                "expires_at".to_owned(),
+                Value::String(expires_at(chrono::Utc::now(), config.ttl)),
+            );
+            "UPDATE { _key: @key } WITH { status: @status, body: @body, expires_at: @expires_at } IN @@collection"
+        }
+        Err(_) => "REMOVE { _key: @key } IN @@collection OPTIONS { ignoreErrors: true }",
+    };
+    let query = ArangoQuery::raw(query.to_owned(), bind_vars);
+    if let Err(err) = db_query::<Value>(query, conn).await {
+        log::error!("{} {} not stored: {}", IDEMPOTENCY_KEY_HEADER, key, err);
+    }
+    result
+}
+
+/// Inserts `record`, unless there is an unexpired one with its key, in one query.
+/// A concurrent insert of the key fails on the primary index, so the one which didn't respond 409.
+async fn reserve(
+    coll: &str,
+    record: &IdempotencyRecord,
+    now: &str,
+    conn: &ArangoConnection,
+) -> Result<Reservation, ApiError> {
+    let mut bind_vars = BTreeMap::new();
+    bind_vars.insert("@collection".to_owned(), Value::String(coll.to_owned()));
+    bind_vars.insert("key".to_owned(), Value::String(record._key.clone()));
+    bind_vars.insert(
+        "record".to_owned(),
+        serde_json::to_value(record).unwrap_or_default(),
+    );
+    bind_vars.insert("now".to_owned(), Value::String(now.to_owned()));
+    let query = ArangoQuery::raw(
+        "UPSERT { _key: @key } INSERT @record UPDATE (OLD.expires_at <= @now ? @record : {}) IN @@collection RETURN { record: NEW, reserved: !OLD || OLD.expires_at <= @now }".to_owned(),
+        bind_vars,
+    );
+    let ar = db_query::<Reservation>(query, conn).await?;
+    ar.result
+        .into_iter()
+        .next()
+        .ok_or_else(|| ApiError::Internal("Empty db response.".to_owned()))
+}
+
+/// The json body of a response, handlers respond with bytes.
+fn response_json(response: &HttpResponse) -> Value {
+    match response.body() {
+        ResponseBody::Body(Body::Bytes(bytes)) | ResponseBody::Other(Body::Bytes(bytes)) => {
+            serde_json::from_slice(bytes).unwrap_or_default()
+        }
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expires_at, fingerprint, response_json, scoped_key};
+    use actix_web::{test::TestRequest, HttpResponse};
+
+    #[test]
+    fn test_fingerprint() {
+        let body = serde_json::json!({ "title": "NU", "content": "Watanuki" });
+        let same = serde_json::json!({ "content": "Watanuki", "title": "NU" });
+        assert_eq!(fingerprint(&body), fingerprint(&same));
+        assert_eq!(40, fingerprint(&body).len());
+        assert_ne!(
+            fingerprint(&body),
+            fingerprint(&serde_json::json!({ "title": "RU" }))
+        );
+    }
+
+    #[test]
+    fn test_scoped_key() {
+        let req = TestRequest::post().uri("/post").to_http_request();
+        let key = scoped_key(&req, &[], "k");
+        assert_eq!(40, key.len());
+        assert_eq!(key, scoped_key(&req, &[], "k"));
+        assert_ne!(key, scoped_key(&req, &[], "l"));
+
+        let author = |author: &str| vec![Some(author.to_owned())];
+        assert_ne!(
+            scoped_key(&req, &author("a"), "k"),
+            scoped_key(&req, &author("b"), "k")
+        );
+
+        let authorized = |token: &str| {
+            TestRequest::post()
+                .uri("/post")
+                .header("Authorization", token)
+                .to_http_request()
+        };
+        assert_ne!(key, scoped_key(&authorized("Bearer a"), &[], "k"));
+        assert_ne!(
+            scoped_key(&authorized("Bearer a"), &[], "k"),
+            scoped_key(&authorized("Bearer b"), &[], "k")
+        );
+    }
+
+    #[test]
+    fn test_expires_at() {
+        let now = chrono::DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        // the lease of a request in progress is short, the stored response is kept longer
+        let config = super::IdempotencyConfig::default();
+        assert_eq!("2020-01-01T00:01:00.000Z", expires_at(now, config.lock_ttl));
+        assert_eq!("2020-01-02T00:00:00.000Z", expires_at(now, config.ttl));
+    }
+
+    #[test]
+    fn test_response_json() {
+        let body = serde_json::json!({ "_key": "42" });
+        assert_eq!(body, response_json(&HttpResponse::Created().json(&body)));
+        assert_eq!(
+            serde_json::Value::Null,
+            response_json(&HttpResponse::NoContent().finish())
+        );
+    }
+}
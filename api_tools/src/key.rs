@@ -0,0 +1,94 @@
+use crate::ApiError;
+use validator::{ValidationError, ValidationErrors};
+
+/// Longest `_key` ArangoDB accepts.
+pub const MAX_KEY_LENGTH: usize = 254;
+
+/// Characters ArangoDB accepts in a `_key` besides ASCII letters and digits.
+const KEY_PUNCTUATION: &str = "_-:.@()+,=;$!*'%";
+
+/// Keys of the routes next to the documents, `/post/count` and `/post/bulk`, a document with one couldn't be reached.
+pub const RESERVED_KEYS: &[&str] = &["count", "bulk"];
+
+/// A random UUIDv4, e.g. `6c3e1b9a-...`, for `#[api(key = "uuid")]`.
+pub fn uuid_key() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// A ULID, sortable by the time of creation, for `#[api(key = "ulid")]`.
+pub fn ulid_key() -> String {
+    ulid::Ulid::new().to_string()
+}
+
+/// Lowercase ASCII letters and digits of `value` joined by `-`, for `#[api(key = "slug(title)")]`.
+/// `Hello, World!` becomes `hello-world`, other characters are dropped.
+pub fn slug_key(value: &str) -> String {
+    let mut slug = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(MAX_KEY_LENGTH);
+    slug.trim_end_matches('-').to_owned()
+}
+
+/// Checks a `_key` against the charset of ArangoDB and the `RESERVED_KEYS`, an empty one is left to the db.
+/// Used by the `_key` injected by `derive_db_fields`, along with its max length.
+pub fn validate_key(key: &str) -> Result<(), ValidationError> {
+    if RESERVED_KEYS.contains(&key) {
+        Err(ValidationError::new("key_reserved"))
+    } else if key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || KEY_PUNCTUATION.contains(c))
+    {
+        Ok(())
+    } else {
+        Err(ValidationError::new("key_charset"))
+    }
+}
+
+/// Rejects a document without `_key` with 422, for `#[api(key = "client")]` and empty slugs.
+pub fn require_key(struct_name: &str, key: &str) -> Result<(), ApiError> {
+    if key.is_empty() {
+        let mut errors = ValidationErrors::new();
+        errors.add("_key", ValidationError::new("required"));
+        Err(ApiError::from_validation(struct_name, &errors))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{require_key, slug_key, ulid_key, uuid_key, validate_key, MAX_KEY_LENGTH};
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(36, uuid_key().len());
+        assert_ne!(uuid_key(), uuid_key());
+        let (first, second) = (ulid_key(), ulid_key());
+        assert_eq!(26, first.len());
+        // the first 10 characters are the time
+        assert!(first[..10] <= second[..10]);
+
+        assert_eq!("hello-world", slug_key("Hello, World!"));
+        assert_eq!("caf-2020", slug_key("  Café 2020 "));
+        assert_eq!("", slug_key("?!"));
+        assert_eq!(MAX_KEY_LENGTH, slug_key(&"a".repeat(300)).len());
+
+        for key in &["", "537130", "a-b_c:d.e@f(g)+h,i=j;k$l!m*n'o%p"] {
+            assert!(validate_key(key).is_ok(), "{}", key);
+        }
+        for key in &["a/b", "a b", "ä", "a#b", "count", "bulk"] {
+            assert!(validate_key(key).is_err(), "{}", key);
+        }
+
+        assert!(require_key("Post", "k").is_ok());
+        let body = require_key("Post", "").unwrap_err().body();
+        assert_eq!(422, body.code);
+        assert!(body.details.contains_key("_key"));
+    }
+}
@@ -1,14 +1,59 @@
 #![forbid(unsafe_code)]
+pub mod author;
+pub mod bulk;
 pub mod db;
+pub mod error;
+pub mod etag;
+pub mod event;
+pub mod hooks;
+pub mod idempotency;
+pub mod key;
+pub mod list;
+pub mod patch;
+pub mod permission;
 
-use actix_web::web::HttpResponse;
+use actix_web::{web::HttpResponse, ResponseError};
 use arangoq::{ArangoConnection, ArangoQuery, ArangoResponse};
+pub use author::*;
+pub use bulk::*;
 pub use db::*;
+pub use error::*;
+pub use etag::*;
+pub use event::*;
 use futures::future::Either;
-use serde::{de::DeserializeOwned, Serialize};
+pub use hooks::*;
+pub use idempotency::*;
+pub use key::*;
+pub use list::*;
+pub use patch::*;
+pub use permission::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Runs a db query, errors of the db are mapped to an `ApiError`.
+/// ```ignore
+/// let ar = db_query::<Organizer>(exist_query, &conn).await?;
+/// ```
+pub async fn db_query<Res>(
+    query: ArangoQuery,
+    conn: &ArangoConnection,
+) -> Result<ArangoResponse<Res>, ApiError>
+where
+    Res: 'static + Serialize + DeserializeOwned + std::fmt::Debug + Send,
+{
+    match query.try_exec::<Res>(conn).await {
+        Ok(ar) => {
+            if !ar.error {
+                Ok(ar)
+            } else {
+                Err(ApiError::from_db(&ar))
+            }
+        }
+        Err(err) => Err(ApiError::Internal(err.to_string())),
+    }
+}
 
 /// You can handle the ok case of a db query with handling Either::Left.
-/// Either::Right is a HttpResponse with the error Result as json in response body.
+/// Either::Right is a HttpResponse with the `ApiError` as json in response body.
 /// Errors are returned as HttpResponses.
 /// ```ignore
 /// # let db_name = "test_db".to_owned();
@@ -32,30 +77,50 @@ pub async fn web_query_ok<Res>(
 where
     Res: 'static + Serialize + DeserializeOwned + std::fmt::Debug + Send,
 {
-    // let dbq = arangoq::DbQuery(query, std::marker::PhantomData::<Res>);
-    // match conn.send(dbq).await {
-    match query.try_exec::<Res>(conn).await {
-        Ok(req_res) =>
-            // match req_res {
-            // Ok(ar) => {
-                {
-                let ar = req_res;
-                if !ar.error {
-                    Either::Left(ar)
-                } else {
-                    let msg = format!("Database Error:{} {}", ar.error_num, ar.error_message);
-                    log::error!("{:#?} -> {}", ar, msg);
-                    Either::Right(HttpResponse::InternalServerError().json(Err::<String, _>(msg)))
-                }
-                }
-            // }
-            // Err(err) => Either::Right(
-            //     HttpResponse::InternalServerError().json(Err::<String, _>(err.to_string())),
-            // ),
-        // },
-        Err(err) => Either::Right(
-            HttpResponse::InternalServerError().json(Err::<String, _>(err.to_string())),
-        ),
+    match db_query::<Res>(query, conn).await {
+        Ok(ar) => Either::Left(ar),
+        Err(err) => Either::Right(err.error_response()),
+    }
+}
+
+/// A whole document, or the attributes kept by a projection.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Projected<T> {
+    Document(T),
+    Partial(serde_json::Value),
+}
+
+/// Same as `db_query`, but a projected query returns partial documents,
+/// which are not deserialized into `Res`.
+pub async fn db_query_projected<Res>(
+    query: ArangoQuery,
+    conn: &ArangoConnection,
+    projected: bool,
+) -> Result<ArangoResponse<Projected<Res>>, ApiError>
+where
+    Res: 'static + Serialize + DeserializeOwned + std::fmt::Debug + Send,
+{
+    if projected {
+        let ar = db_query::<serde_json::Value>(query, conn).await?;
+        Ok(map_result(ar, Projected::Partial))
+    } else {
+        let ar = db_query::<Res>(query, conn).await?;
+        Ok(map_result(ar, Projected::Document))
+    }
+}
+
+fn map_result<T, U>(ar: ArangoResponse<T>, f: impl FnMut(T) -> U) -> ArangoResponse<U> {
+    ArangoResponse {
+        result: ar.result.into_iter().map(f).collect(),
+        has_more: ar.has_more,
+        cached: ar.cached,
+        extra: ar.extra,
+        error: ar.error,
+        code: ar.code,
+        error_message: ar.error_message,
+        error_num: ar.error_num,
+        id: ar.id,
     }
 }
 
@@ -77,6 +142,7 @@ mod tests {
 
     #[derive(Debug)]
     struct SuperZed {
+        #[allow(dead_code)]
         my_name: String,
     }
 
@@ -0,0 +1,983 @@
+use actix_web::{web, HttpRequest};
+use arangoq::ArangoQuery;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Page size used when the request doesn't specify a `limit`.
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+/// Largest `limit` a client may ask for.
+pub const MAX_PAGE_LIMIT: usize = 1000;
+
+/// Query parameters controlling paging.
+pub const PAGE_PARAMS: [&str; 3] = ["offset", "limit", "cursor"];
+/// Query parameters of a list request which are not field filters.
+pub const RESERVED_PARAMS: [&str; 6] = [
+    "offset",
+    "limit",
+    "cursor",
+    "sort",
+    "fields",
+    "include_deleted",
+];
+
+/// Page size settings of the list handlers.
+/// Register it as app data to override the defaults:
+/// ```ignore
+/// App::new().data(PageConfig { default_limit: 25, max_limit: 500 })
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageConfig {
+    pub default_limit: usize,
+    pub max_limit: usize,
+}
+impl Default for PageConfig {
+    fn default() -> Self {
+        PageConfig {
+            default_limit: DEFAULT_PAGE_LIMIT,
+            max_limit: MAX_PAGE_LIMIT,
+        }
+    }
+}
+
+/// Paging query parameters of a list request.
+/// `cursor` is an opaque value taken from the `next` or `prev` link of a previous page,
+/// it takes precedence over `offset` and `limit`.
+#[derive(Debug, Default, Deserialize)]
+pub struct PageParams {
+    #[serde(default)]
+    pub offset: Option<usize>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub cursor: Option<String>,
+}
+
+/// The slice of a collection a list handler returns.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PageWindow {
+    #[serde(rename = "o")]
+    pub offset: usize,
+    #[serde(rename = "l")]
+    pub limit: usize,
+}
+
+impl PageWindow {
+    /// Resolves the window from the query string of the request.
+    /// The limit is capped by the `PageConfig` registered as app data, if any.
+    pub fn from_request(req: &HttpRequest) -> Result<Self, String> {
+        let config = req
+            .app_data::<web::Data<PageConfig>>()
+            .map(|config| config.get_ref().clone())
+            .unwrap_or_default();
+        let params = web::Query::<PageParams>::from_query(req.query_string())
+            .map_err(|err| format!("Invalid paging parameters: {}", err))?
+            .into_inner();
+        Self::resolve(&params, &config)
+    }
+
+    pub fn resolve(params: &PageParams, config: &PageConfig) -> Result<Self, String> {
+        let window = match &params.cursor {
+            Some(cursor) => Self::decode(cursor)?,
+            None => PageWindow {
+                offset: params.offset.unwrap_or_default(),
+                limit: params.limit.unwrap_or(config.default_limit),
+            },
+        };
+        Ok(PageWindow {
+            offset: window.offset,
+            limit: window.limit.max(1).min(config.max_limit),
+        })
+    }
+
+    /// Encodes the window into an opaque cursor.
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        base64::encode_config(&json, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, String> {
+        base64::decode_config(cursor, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| format!("Invalid cursor: {}", cursor))
+    }
+
+    /// The following page, `None` past the last one, or if its offset would overflow.
+    pub fn next(&self, total: usize) -> Option<Self> {
+        let offset = self.offset.checked_add(self.limit)?;
+        if offset < total {
+            Some(PageWindow {
+                offset,
+                limit: self.limit,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn prev(&self) -> Option<Self> {
+        if self.offset > 0 {
+            Some(PageWindow {
+                offset: self.offset.saturating_sub(self.limit),
+                limit: self.limit,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Response envelope of the list handlers.
+/// `next` and `prev` are links to the neighbouring pages, relative to the host.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Page<T> {
+    pub collection: Vec<T>,
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub next: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub prev: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn new(req: &HttpRequest, window: PageWindow, collection: Vec<T>, total: usize) -> Self {
+        Page {
+            collection,
+            total,
+            offset: window.offset,
+            limit: window.limit,
+            next: window.next(total).map(|next| page_link(req, &next)),
+            prev: window.prev().map(|prev| page_link(req, &prev)),
+        }
+    }
+
+    /// Value of the `Content-Range` header, e.g. `items 20-29/42`, or `items */42` for an empty page.
+    pub fn content_range(&self) -> String {
+        if self.collection.is_empty() {
+            format!("items */{}", self.total)
+        } else {
+            let last = self.offset + self.collection.len() - 1;
+            format!("items {}-{}/{}", self.offset, last, self.total)
+        }
+    }
+}
+
+/// Link to the same resource with the same query, except for paging which is replaced by a cursor.
+fn page_link(req: &HttpRequest, window: &PageWindow) -> String {
+    let mut query: Vec<(String, String)> =
+        serde_urlencoded::from_str(req.query_string()).unwrap_or_default();
+    query.retain(|(name, _)| !PAGE_PARAMS.contains(&name.as_str()));
+    query.push(("cursor".to_owned(), window.encode()));
+    format!(
+        "{}?{}",
+        req.path(),
+        serde_urlencoded::to_string(&query).unwrap_or_default()
+    )
+}
+
+/// Comparison operators of the filter query parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    In,
+    NotIn,
+}
+
+impl FilterOp {
+    fn from_suffix(suffix: &str) -> Option<Self> {
+        match suffix {
+            "eq" => Some(FilterOp::Eq),
+            "ne" => Some(FilterOp::Ne),
+            "gt" => Some(FilterOp::Gt),
+            "lt" => Some(FilterOp::Lt),
+            "ge" => Some(FilterOp::Ge),
+            "le" => Some(FilterOp::Le),
+            "in" => Some(FilterOp::In),
+            "not_in" => Some(FilterOp::NotIn),
+            _ => None,
+        }
+    }
+
+    pub fn aql(self) -> &'static str {
+        match self {
+            FilterOp::Eq => "==",
+            FilterOp::Ne => "!=",
+            FilterOp::Gt => ">",
+            FilterOp::Lt => "<",
+            FilterOp::Ge => ">=",
+            FilterOp::Le => "<=",
+            FilterOp::In => "IN",
+            FilterOp::NotIn => "NOT IN",
+        }
+    }
+
+    /// Operator comparing the elements of an array attribute, e.g. `tags ANY IN ["a", "b"]`.
+    pub fn array_aql(self) -> &'static str {
+        match self {
+            FilterOp::Eq => "ANY ==",
+            FilterOp::Ne => "NONE ==",
+            FilterOp::Gt => "ANY >",
+            FilterOp::Lt => "ANY <",
+            FilterOp::Ge => "ANY >=",
+            FilterOp::Le => "ANY <=",
+            FilterOp::In => "ANY IN",
+            FilterOp::NotIn => "NONE IN",
+        }
+    }
+
+    /// `in` and `not_in` take a comma separated list of values.
+    pub fn takes_list(self) -> bool {
+        matches!(self, FilterOp::In | FilterOp::NotIn)
+    }
+}
+
+/// A field filter query parameter, e.g. `title=foo`, `number__gt=5` or `tags__in=a,b`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterParam {
+    pub field: String,
+    pub op: FilterOp,
+    pub value: String,
+}
+
+impl FilterParam {
+    pub fn parse(name: &str, value: &str) -> Self {
+        let (field, op) = match name.rfind("__") {
+            Some(idx) => match FilterOp::from_suffix(&name[idx + 2..]) {
+                Some(op) => (&name[..idx], op),
+                None => (name, FilterOp::Eq),
+            },
+            None => (name, FilterOp::Eq),
+        };
+        FilterParam {
+            field: field.to_owned(),
+            op,
+            value: value.to_owned(),
+        }
+    }
+
+    /// Collects the filters from the query string of the request, paging and other reserved
+    /// parameters are skipped.
+    pub fn from_request(req: &HttpRequest) -> Result<Vec<Self>, String> {
+        let query: Vec<(String, String)> = serde_urlencoded::from_str(req.query_string())
+            .map_err(|err| format!("Invalid query string: {}", err))?;
+        Ok(query
+            .iter()
+            .filter(|(name, _)| !RESERVED_PARAMS.contains(&name.as_str()))
+            .map(|(name, value)| Self::parse(name, value))
+            .collect())
+    }
+
+    /// Converts the value of the filter to the type `T` of the filtered field,
+    /// or a list of `T` for `in` and `not_in`.
+    pub fn typed_value<T: DeserializeOwned + Serialize>(&self) -> Result<Value, String> {
+        let value = if self.op.takes_list() {
+            let values = self
+                .value
+                .split(',')
+                .map(|value| parse_value::<T>(value))
+                .collect::<Option<Vec<T>>>();
+            values.map(|values| serde_json::to_value(&values).unwrap_or_default())
+        } else {
+            parse_value::<T>(&self.value)
+                .map(|value| serde_json::to_value(&value).unwrap_or_default())
+        };
+        value.ok_or_else(|| format!("Invalid value for {}: {}", self.field, self.value))
+    }
+}
+
+/// Query parameters are untyped, so the value is tried as json first, e.g. numbers and booleans,
+/// then as a plain string.
+fn parse_value<T: DeserializeOwned>(value: &str) -> Option<T> {
+    serde_json::from_str::<T>(value)
+        .or_else(|_| serde_json::from_value::<T>(Value::String(value.to_owned())))
+        .ok()
+}
+
+/// An attribute to order the list by, `sort=-created,title` sorts by `created` descending then by `title`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub field: String,
+    pub descending: bool,
+}
+
+impl SortKey {
+    /// Parses a comma separated list of fields, each optionally prefixed by `-` for descending order.
+    /// Only the `sortable` fields are accepted.
+    pub fn parse_list(spec: &str, sortable: &[&str]) -> Result<Vec<Self>, String> {
+        spec.split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(|field| {
+                let (field, descending) = match field.strip_prefix('-') {
+                    Some(field) => (field, true),
+                    None => (field.strip_prefix('+').unwrap_or(field), false),
+                };
+                if sortable.contains(&field) {
+                    Ok(SortKey {
+                        field: field.to_owned(),
+                        descending,
+                    })
+                } else {
+                    Err(format!("Can not sort on unknown field: {}", field))
+                }
+            })
+            .collect()
+    }
+
+    /// The `sort` query parameter of the request, or `default_sort` if there is none.
+    pub fn from_request(
+        req: &HttpRequest,
+        sortable: &[&str],
+        default_sort: &str,
+    ) -> Result<Vec<Self>, String> {
+        let query: Vec<(String, String)> = serde_urlencoded::from_str(req.query_string())
+            .map_err(|err| format!("Invalid query string: {}", err))?;
+        let spec = query
+            .iter()
+            .find(|(name, _)| name == "sort")
+            .map_or(default_sort, |(_, spec)| spec.as_str());
+        Self::parse_list(spec, sortable)
+    }
+}
+
+/// A document written by `ListQuery::upsert`, `created` if there was none with its key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Upserted<T> {
+    pub doc: T,
+    pub created: bool,
+}
+
+/// Builds the AQL of a list handler.
+/// arangoq's query builder can't skip documents, so the query is composed here
+/// and executed as a raw query with `fullCount` enabled.
+/// ```ignore
+/// let query = ListQuery::new(coll.as_str()).filter_eq("author", &author).build(window);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ListQuery {
+    filters: Vec<String>,
+    sort: Vec<SortKey>,
+    keep: Vec<String>,
+    preserve: Vec<String>,
+    bind_vars: BTreeMap<String, Value>,
+}
+
+impl ListQuery {
+    pub fn new(collection_name: &str) -> Self {
+        let mut bind_vars = BTreeMap::new();
+        bind_vars.insert(
+            "@collection".to_owned(),
+            Value::String(collection_name.to_owned()),
+        );
+        ListQuery {
+            filters: vec![],
+            sort: vec![],
+            keep: vec![],
+            preserve: vec![],
+            bind_vars,
+        }
+    }
+
+    /// Only keep documents where `field` equals `value`.
+    pub fn filter_eq<T: Serialize>(self, field: &str, value: &T) -> Self {
+        self.filter(field, FilterOp::Eq, value)
+    }
+
+    /// Only keep documents where the comparison of `field` to `value` holds.
+    /// `field` is inserted into the query as is, it must be a known attribute name.
+    pub fn filter<T: Serialize>(self, field: &str, op: FilterOp, value: &T) -> Self {
+        self.push_filter(field, op.aql(), value)
+    }
+
+    /// Same as `filter`, for array attributes, the comparison must hold for any of the elements.
+    pub fn filter_any<T: Serialize>(self, field: &str, op: FilterOp, value: &T) -> Self {
+        self.push_filter(field, op.array_aql(), value)
+    }
+
+    fn push_filter<T: Serialize>(mut self, field: &str, op: &str, value: &T) -> Self {
+        let bind_var_name = format!("filterVar{}", self.bind_vars.len());
+        self.bind_vars.insert(
+            bind_var_name.clone(),
+            serde_json::to_value(value).unwrap_or_default(),
+        );
+        self.filters
+            .push(format!("FILTER item.{} {} @{}", field, op, bind_var_name));
+        self
+    }
+
+    /// Skips the soft deleted documents, those with a `deleted_at`.
+    pub fn exclude_deleted(mut self) -> Self {
+        self.filters.push("FILTER !item.deleted_at".to_owned());
+        self
+    }
+
+    /// Only the soft deleted documents, e.g. to restore one.
+    pub fn only_deleted(mut self) -> Self {
+        self.filters.push("FILTER item.deleted_at".to_owned());
+        self
+    }
+
+    /// Orders the documents by the `keys`, then by `_key` so pages are stable.
+    /// Fields are inserted into the query as is, they must be known attribute names.
+    pub fn sort(mut self, keys: &[SortKey]) -> Self {
+        self.sort.extend_from_slice(keys);
+        self
+    }
+
+    /// Only return the given attributes of the documents, `_key`, `_id` and `_rev` are always kept.
+    /// No fields means whole documents.
+    pub fn keep(mut self, fields: &[String]) -> Self {
+        self.keep.extend_from_slice(fields);
+        self
+    }
+
+    /// A page of the matching documents.
+    /// The whole page is returned in the first batch, `try_exec` doesn't follow the cursor.
+    pub fn build(self, window: PageWindow) -> ArangoQuery {
+        let mut sort: Vec<String> = self
+            .sort
+            .iter()
+            .map(|key| {
+                let direction = if key.descending { "DESC" } else { "ASC" };
+                format!("item.{} {}", key.field, direction)
+            })
+            .collect();
+        if !self.sort.iter().any(|key| key.field == "_key") {
+            sort.push("item._key ASC".to_owned());
+        }
+
+        let mut raw_query = vec!["FOR item IN @@collection".to_owned()];
+        raw_query.extend(self.filters);
+        raw_query.push(format!("SORT {}", sort.join(", ")));
+        raw_query.push("LIMIT @offset, @limit".to_owned());
+
+        let mut bind_vars = self.bind_vars;
+        bind_vars.insert("offset".to_owned(), Value::from(window.offset));
+        bind_vars.insert("limit".to_owned(), Value::from(window.limit));
+        raw_query.push(Self::return_clause(self.keep, &mut bind_vars));
+
+        ArangoQuery::raw_batched(raw_query.join(" "), bind_vars, window.limit).full_count(true)
+    }
+
+    /// The first matching document, e.g. to fetch by `_key`.
+    pub fn first(self) -> ArangoQuery {
+        let mut raw_query = vec!["FOR item IN @@collection".to_owned()];
+        raw_query.extend(self.filters);
+        raw_query.push("LIMIT 1".to_owned());
+
+        let mut bind_vars = self.bind_vars;
+        raw_query.push(Self::return_clause(self.keep, &mut bind_vars));
+
+        ArangoQuery::raw(raw_query.join(" "), bind_vars)
+    }
+
+    /// All the matching documents, e.g. those of a list of keys.
+    pub fn all(self) -> ArangoQuery {
+        let mut raw_query = vec!["FOR item IN @@collection".to_owned()];
+        raw_query.extend(self.filters);
+
+        let mut bind_vars = self.bind_vars;
+        raw_query.push(Self::return_clause(self.keep, &mut bind_vars));
+
+        ArangoQuery::raw(raw_query.join(" "), bind_vars)
+    }
+
+    /// The number of matching documents, sorting and projection are ignored.
+    pub fn count(self) -> ArangoQuery {
+        let mut raw_query = vec!["FOR item IN @@collection".to_owned()];
+        raw_query.extend(self.filters);
+        raw_query.push("COLLECT WITH COUNT INTO total RETURN total".to_owned());
+
+        ArangoQuery::raw(raw_query.join(" "), self.bind_vars)
+    }
+
+    /// The stored values of `fields` survive a `replace`, whatever `doc` has.
+    pub fn preserve(mut self, fields: &[&str]) -> Self {
+        self.preserve = fields.iter().map(|field| (*field).to_owned()).collect();
+        self
+    }
+
+    /// Replaces the matching documents with `doc`, returns the new ones.
+    /// With a `rev` the db responds with a conflict (1200) if it is not the current revision.
+    pub fn replace<T: Serialize>(self, doc: &T, rev: Option<&str>) -> ArangoQuery {
+        let mut raw_query = vec!["FOR item IN @@collection".to_owned()];
+        raw_query.extend(self.filters);
+        let mut bind_vars = self.bind_vars;
+        bind_vars.insert(
+            "doc".to_owned(),
+            serde_json::to_value(doc).unwrap_or_default(),
+        );
+        let with = if self.preserve.is_empty() {
+            "@doc".to_owned()
+        } else {
+            bind_vars.insert("preserve".to_owned(), Value::from(self.preserve));
+            "MERGE(@doc, KEEP(item, @preserve))".to_owned()
+        };
+        raw_query.push(format!(
+            "REPLACE {} WITH {} IN @@collection{} RETURN NEW",
+            Self::selector(rev, &mut bind_vars),
+            with,
+            Self::rev_options(rev)
+        ));
+
+        ArangoQuery::raw(raw_query.join(" "), bind_vars)
+    }
+
+    /// Merges `patch` into the matching documents, a `null` removes the attribute, returns the new ones.
+    /// With a `rev` the db responds with a conflict (1200) if it is not the current revision.
+    pub fn update<T: Serialize>(self, patch: &T, rev: Option<&str>) -> ArangoQuery {
+        let mut raw_query = vec!["FOR item IN @@collection".to_owned()];
+        raw_query.extend(self.filters);
+        let mut bind_vars = self.bind_vars;
+        bind_vars.insert(
+            "patch".to_owned(),
+            serde_json::to_value(patch).unwrap_or_default(),
+        );
+        raw_query.push(format!(
+            "UPDATE {} WITH @patch IN @@collection OPTIONS {{ keepNull: false{} }} RETURN NEW",
+            Self::selector(rev, &mut bind_vars),
+            if rev.is_some() {
+                ", ignoreRevs: false"
+            } else {
+                ""
+            }
+        ));
+
+        ArangoQuery::raw(raw_query.join(" "), bind_vars)
+    }
+
+    /// Inserts `insert`, or replaces the document matching all the `search` attributes with `replace`,
+    /// in one query, see `Upserted`. The filters are ignored, the kept fields are preserved.
+    /// Attribute names are inserted into the query as is, they must be known attribute names.
+    pub fn upsert<T: Serialize>(
+        self,
+        search: &[(&str, Value)],
+        insert: &T,
+        replace: &T,
+    ) -> ArangoQuery {
+        let mut bind_vars = self.bind_vars;
+        let search = search
+            .iter()
+            .enumerate()
+            .map(|(idx, (field, value))| {
+                let bind_var_name = format!("searchVar{}", idx);
+                bind_vars.insert(bind_var_name.clone(), value.clone());
+                format!("{}: @{}", field, bind_var_name)
+            })
+            .collect::<Vec<String>>();
+        bind_vars.insert(
+            "insert".to_owned(),
+            serde_json::to_value(insert).unwrap_or_default(),
+        );
+        bind_vars.insert(
+            "replace".to_owned(),
+            serde_json::to_value(replace).unwrap_or_default(),
+        );
+        let with = if self.preserve.is_empty() {
+            "@replace".to_owned()
+        } else {
+            bind_vars.insert("preserve".to_owned(), Value::from(self.preserve));
+            "MERGE(@replace, KEEP(OLD, @preserve))".to_owned()
+        };
+        let raw_query = format!(
+            "UPSERT {{ {} }} INSERT @insert REPLACE {} IN @@collection RETURN {{ doc: NEW, created: !OLD }}",
+            search.join(", "),
+            with
+        );
+
+        ArangoQuery::raw(raw_query, bind_vars)
+    }
+
+    /// Removes the matching documents, returns the removed ones.
+    /// With a `rev` the db responds with a conflict (1200) if it is not the current revision.
+    pub fn remove(self, rev: Option<&str>) -> ArangoQuery {
+        let mut raw_query = vec!["FOR item IN @@collection".to_owned()];
+        raw_query.extend(self.filters);
+        let mut bind_vars = self.bind_vars;
+        raw_query.push(format!(
+            "REMOVE {} IN @@collection{} RETURN OLD",
+            Self::selector(rev, &mut bind_vars),
+            Self::rev_options(rev)
+        ));
+
+        ArangoQuery::raw(raw_query.join(" "), bind_vars)
+    }
+
+    fn selector(rev: Option<&str>, bind_vars: &mut BTreeMap<String, Value>) -> String {
+        match rev {
+            Some(rev) => {
+                bind_vars.insert("rev".to_owned(), Value::String(rev.to_owned()));
+                "{ _key: item._key, _rev: @rev }".to_owned()
+            }
+            None => "item".to_owned(),
+        }
+    }
+
+    fn rev_options(rev: Option<&str>) -> &'static str {
+        if rev.is_some() {
+            " OPTIONS { ignoreRevs: false }"
+        } else {
+            ""
+        }
+    }
+
+    fn return_clause(keep: Vec<String>, bind_vars: &mut BTreeMap<String, Value>) -> String {
+        if keep.is_empty() {
+            "RETURN item".to_owned()
+        } else {
+            let mut keep = keep;
+            keep.extend(vec!["_key".to_owned(), "_id".to_owned(), "_rev".to_owned()]);
+            bind_vars.insert("keep".to_owned(), Value::from(keep));
+            "RETURN KEEP(item, @keep)".to_owned()
+        }
+    }
+}
+
+/// The `include_deleted` query parameter, `true` also lists the soft deleted documents.
+pub fn include_deleted(req: &HttpRequest) -> Result<bool, String> {
+    let query: Vec<(String, String)> = serde_urlencoded::from_str(req.query_string())
+        .map_err(|err| format!("Invalid query string: {}", err))?;
+    match query.iter().find(|(name, _)| name == "include_deleted") {
+        Some((_, value)) => value
+            .parse()
+            .map_err(|_| format!("Invalid value for include_deleted: {}", value)),
+        None => Ok(false),
+    }
+}
+
+/// The `fields` query parameter, a comma separated list of the attributes to return.
+/// Only the `known` fields are accepted.
+pub fn fields_from_request(req: &HttpRequest, known: &[&str]) -> Result<Vec<String>, String> {
+    let query: Vec<(String, String)> = serde_urlencoded::from_str(req.query_string())
+        .map_err(|err| format!("Invalid query string: {}", err))?;
+    query
+        .iter()
+        .filter(|(name, _)| name == "fields")
+        .flat_map(|(_, fields)| fields.split(','))
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            if known.contains(&field) {
+                Ok(field.to_owned())
+            } else {
+                Err(format!("Unknown field in fields: {}", field))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        FilterOp, FilterParam, ListQuery, Page, PageConfig, PageParams, PageWindow, SortKey,
+    };
+
+    #[test]
+    fn test_resolve_window() {
+        let config = PageConfig {
+            default_limit: 20,
+            max_limit: 50,
+        };
+
+        let window = PageWindow::resolve(&PageParams::default(), &config).unwrap();
+        assert_eq!(
+            PageWindow {
+                offset: 0,
+                limit: 20
+            },
+            window
+        );
+
+        let params = PageParams {
+            offset: Some(40),
+            limit: Some(500),
+            cursor: None,
+        };
+        let window = PageWindow::resolve(&params, &config).unwrap();
+        assert_eq!(
+            PageWindow {
+                offset: 40,
+                limit: 50
+            },
+            window
+        );
+
+        let cursor = PageWindow {
+            offset: 60,
+            limit: 10,
+        }
+        .encode();
+        let params = PageParams {
+            offset: Some(40),
+            limit: None,
+            cursor: Some(cursor),
+        };
+        let window = PageWindow::resolve(&params, &config).unwrap();
+        assert_eq!(
+            PageWindow {
+                offset: 60,
+                limit: 10
+            },
+            window
+        );
+
+        let params = PageParams {
+            offset: None,
+            limit: None,
+            cursor: Some("nope".to_owned()),
+        };
+        assert!(PageWindow::resolve(&params, &config).is_err());
+    }
+
+    #[test]
+    fn test_next_prev() {
+        let window = PageWindow {
+            offset: 10,
+            limit: 20,
+        };
+        assert_eq!(
+            Some(PageWindow {
+                offset: 30,
+                limit: 20
+            }),
+            window.next(31)
+        );
+        assert_eq!(None, window.next(30));
+        let last = PageWindow {
+            offset: usize::MAX,
+            limit: 20,
+        };
+        assert_eq!(None, last.next(usize::MAX));
+        assert_eq!(
+            Some(PageWindow {
+                offset: 0,
+                limit: 20
+            }),
+            window.prev()
+        );
+        assert_eq!(
+            None,
+            PageWindow {
+                offset: 0,
+                limit: 20
+            }
+            .prev()
+        );
+    }
+
+    #[test]
+    fn test_filter_param() {
+        let param = FilterParam::parse("number__gt", "5");
+        assert_eq!(("number", FilterOp::Gt), (param.field.as_str(), param.op));
+        assert_eq!(Ok(serde_json::json!(5)), param.typed_value::<u64>());
+        assert!(FilterParam::parse("number", "five")
+            .typed_value::<u64>()
+            .is_err());
+
+        let param = FilterParam::parse("title", "5");
+        assert_eq!(("title", FilterOp::Eq), (param.field.as_str(), param.op));
+        assert_eq!(Ok(serde_json::json!("5")), param.typed_value::<String>());
+
+        let param = FilterParam::parse("tags__not_in", "a,b");
+        assert_eq!(("tags", FilterOp::NotIn), (param.field.as_str(), param.op));
+        assert_eq!(
+            Ok(serde_json::json!(["a", "b"])),
+            param.typed_value::<String>()
+        );
+
+        let param = FilterParam::parse("some__thing", "x");
+        assert_eq!(
+            ("some__thing", FilterOp::Eq),
+            (param.field.as_str(), param.op)
+        );
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let sortable = ["title", "number", "_key"];
+        let keys = SortKey::parse_list("-number, title", &sortable).unwrap();
+        assert_eq!(
+            vec![
+                SortKey {
+                    field: "number".to_owned(),
+                    descending: true
+                },
+                SortKey {
+                    field: "title".to_owned(),
+                    descending: false
+                }
+            ],
+            keys
+        );
+        assert_eq!(Ok(vec![]), SortKey::parse_list("", &sortable));
+        assert!(SortKey::parse_list("-colour", &sortable).is_err());
+    }
+
+    #[test]
+    fn test_list_query() {
+        let query = ListQuery::new("posts")
+            .filter_eq("author", &"a@b.c")
+            .sort(&[SortKey {
+                field: "title".to_owned(),
+                descending: true,
+            }])
+            .keep(&["title".to_owned()])
+            .build(PageWindow {
+                offset: 20,
+                limit: 10,
+            });
+        assert_eq!(
+            serde_json::json!({
+                "query": "FOR item IN @@collection FILTER item.author == @filterVar1 SORT item.title DESC, item._key ASC LIMIT @offset, @limit RETURN KEEP(item, @keep)",
+                "bindVars": {
+                    "@collection": "posts",
+                    "filterVar1": "a@b.c",
+                    "offset": 20,
+                    "limit": 10,
+                    "keep": ["title", "_key", "_id", "_rev"],
+                },
+                "batchSize": 10,
+                "options": { "fullCount": true },
+            }),
+            serde_json::to_value(&query).unwrap()
+        );
+
+        let query = ListQuery::new("posts").filter_eq("_key", &"42").first();
+        assert_eq!(
+            serde_json::json!({
+                "query": "FOR item IN @@collection FILTER item._key == @filterVar1 LIMIT 1 RETURN item",
+                "bindVars": { "@collection": "posts", "filterVar1": "42" },
+            }),
+            serde_json::to_value(&query).unwrap()
+        );
+
+        let query = ListQuery::new("posts")
+            .filter_eq("author", &"a@b.c")
+            .sort(&[SortKey {
+                field: "title".to_owned(),
+                descending: true,
+            }])
+            .count();
+        assert_eq!(
+            serde_json::json!({
+                "query": "FOR item IN @@collection FILTER item.author == @filterVar1 COLLECT WITH COUNT INTO total RETURN total",
+                "bindVars": { "@collection": "posts", "filterVar1": "a@b.c" },
+            }),
+            serde_json::to_value(&query).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_write_queries() {
+        let query = ListQuery::new("posts")
+            .filter_eq("_key", &"42")
+            .replace(&serde_json::json!({ "title": "NU" }), Some("_abc"));
+        assert_eq!(
+            serde_json::json!({
+                "query": "FOR item IN @@collection FILTER item._key == @filterVar1 REPLACE { _key: item._key, _rev: @rev } WITH @doc IN @@collection OPTIONS { ignoreRevs: false } RETURN NEW",
+                "bindVars": {
+                    "@collection": "posts",
+                    "filterVar1": "42",
+                    "doc": { "title": "NU" },
+                    "rev": "_abc",
+                },
+            }),
+            serde_json::to_value(&query).unwrap()
+        );
+
+        let query = ListQuery::new("posts")
+            .filter_eq("_key", &"42")
+            .preserve(&["created_at"])
+            .replace(&serde_json::json!({ "title": "NU" }), None);
+        assert_eq!(
+            serde_json::json!({
+                "query": "FOR item IN @@collection FILTER item._key == @filterVar1 REPLACE item WITH MERGE(@doc, KEEP(item, @preserve)) IN @@collection RETURN NEW",
+                "bindVars": {
+                    "@collection": "posts",
+                    "filterVar1": "42",
+                    "doc": { "title": "NU" },
+                    "preserve": ["created_at"],
+                },
+            }),
+            serde_json::to_value(&query).unwrap()
+        );
+
+        let query = ListQuery::new("posts")
+            .filter_eq("_key", &"42")
+            .only_deleted()
+            .update(&serde_json::json!({ "deleted_at": null }), Some("_abc"));
+        assert_eq!(
+            serde_json::json!({
+                "query": "FOR item IN @@collection FILTER item._key == @filterVar1 FILTER item.deleted_at UPDATE { _key: item._key, _rev: @rev } WITH @patch IN @@collection OPTIONS { keepNull: false, ignoreRevs: false } RETURN NEW",
+                "bindVars": {
+                    "@collection": "posts",
+                    "filterVar1": "42",
+                    "patch": { "deleted_at": null },
+                    "rev": "_abc",
+                },
+            }),
+            serde_json::to_value(&query).unwrap()
+        );
+
+        let query = ListQuery::new("posts").preserve(&["created_at"]).upsert(
+            &[
+                ("title", serde_json::json!("NU")),
+                ("author", serde_json::json!("a")),
+            ],
+            &serde_json::json!({ "title": "NU", "created_at": "now" }),
+            &serde_json::json!({ "title": "NU" }),
+        );
+        assert_eq!(
+            serde_json::json!({
+                "query": "UPSERT { title: @searchVar0, author: @searchVar1 } INSERT @insert REPLACE MERGE(@replace, KEEP(OLD, @preserve)) IN @@collection RETURN { doc: NEW, created: !OLD }",
+                "bindVars": {
+                    "@collection": "posts",
+                    "searchVar0": "NU",
+                    "searchVar1": "a",
+                    "insert": { "title": "NU", "created_at": "now" },
+                    "replace": { "title": "NU" },
+                    "preserve": ["created_at"],
+                },
+            }),
+            serde_json::to_value(&query).unwrap()
+        );
+
+        let query = ListQuery::new("posts")
+            .filter_eq("_key", &"42")
+            .remove(None);
+        assert_eq!(
+            serde_json::json!({
+                "query": "FOR item IN @@collection FILTER item._key == @filterVar1 REMOVE item IN @@collection RETURN OLD",
+                "bindVars": { "@collection": "posts", "filterVar1": "42" },
+            }),
+            serde_json::to_value(&query).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_content_range() {
+        let page = Page {
+            collection: vec![1, 2, 3],
+            total: 42,
+            offset: 20,
+            limit: 10,
+            next: None,
+            prev: None,
+        };
+        assert_eq!("items 20-22/42", page.content_range());
+
+        let page = Page {
+            collection: vec![],
+            ..page
+        };
+        assert_eq!("items */42", page.content_range());
+    }
+}
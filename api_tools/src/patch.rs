@@ -0,0 +1,143 @@
+use crate::ApiError;
+use actix_web::{HttpMessage, HttpRequest};
+use serde_json::Value;
+
+/// Content type of a RFC 7396 merge patch.
+pub const MERGE_PATCH_JSON: &str = "application/merge-patch+json";
+/// Content type of a RFC 6902 json patch.
+pub const JSON_PATCH_JSON: &str = "application/json-patch+json";
+
+/// The formats of a patch `update_` takes, chosen by the `Content-Type` of the request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatchFormat {
+    /// RFC 7396, the object is merged into the document, a `null` removes the field.
+    /// Plain `application/json` is taken as a merge patch.
+    Merge,
+    /// RFC 6902, a list of `add`, `remove`, `replace`, `move`, `copy` and `test` operations.
+    Json,
+}
+
+impl PatchFormat {
+    /// Other content types are rejected with 415.
+    pub fn from_request(req: &HttpRequest) -> Result<Self, ApiError> {
+        let mime = req.mime_type().ok().flatten();
+        match mime.as_ref().map(|mime| mime.essence_str()) {
+            Some(MERGE_PATCH_JSON) | Some("application/json") => Ok(PatchFormat::Merge),
+            Some(JSON_PATCH_JSON) => Ok(PatchFormat::Json),
+            other => Err(ApiError::UnsupportedMediaType(format!(
+                "Unsupported patch format: {}, expected {} or {}.",
+                other.unwrap_or("none"),
+                MERGE_PATCH_JSON,
+                JSON_PATCH_JSON
+            ))),
+        }
+    }
+
+    /// Applies `patch` to `doc`, a json patch is all or nothing.
+    /// A malformed patch is a bad request, one which doesn't fit the document, e.g. a failing `test`, a conflict.
+    pub fn apply(self, doc: &mut Value, patch: &Value) -> Result<(), ApiError> {
+        match self {
+            PatchFormat::Merge => {
+                json_patch::merge(doc, patch);
+                Ok(())
+            }
+            PatchFormat::Json => {
+                let operations = json_patch::from_value(patch.clone())
+                    .map_err(|err| ApiError::BadRequest(format!("Invalid json patch: {}", err)))?;
+                json_patch::patch(doc, &operations).map_err(|err| {
+                    ApiError::Conflict(format!("Json patch can not be applied: {}", err))
+                })
+            }
+        }
+    }
+}
+
+/// The document to store for a patched model, `doc` serialized from it,
+/// with the attributes of the `patched` document which are none of its `fields`.
+/// A model which drops extra attributes would otherwise delete those it doesn't know.
+pub fn with_unknown_attributes(mut doc: Value, patched: &Value, fields: &[&str]) -> Value {
+    if let (Value::Object(doc), Value::Object(patched)) = (&mut doc, patched) {
+        for (name, value) in patched {
+            let known = name == "_id" || fields.contains(&name.as_str());
+            if !known && !doc.contains_key(name) {
+                doc.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{with_unknown_attributes, PatchFormat, JSON_PATCH_JSON, MERGE_PATCH_JSON};
+    use crate::ApiError;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_patch_format() {
+        for (content_type, format) in &[
+            (MERGE_PATCH_JSON, PatchFormat::Merge),
+            ("application/json; charset=utf-8", PatchFormat::Merge),
+            (JSON_PATCH_JSON, PatchFormat::Json),
+        ] {
+            let req = TestRequest::default()
+                .header("Content-Type", *content_type)
+                .to_http_request();
+            assert_eq!(Ok(*format), PatchFormat::from_request(&req));
+        }
+
+        let req = TestRequest::default()
+            .header("Content-Type", "text/plain")
+            .to_http_request();
+        assert!(matches!(
+            PatchFormat::from_request(&req),
+            Err(ApiError::UnsupportedMediaType(_))
+        ));
+        let req = TestRequest::default().to_http_request();
+        assert!(PatchFormat::from_request(&req).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut doc = serde_json::json!({ "title": "NU", "tags": ["a", "b"] });
+        PatchFormat::Merge
+            .apply(&mut doc, &serde_json::json!({ "title": null, "number": 1 }))
+            .unwrap();
+        assert_eq!(serde_json::json!({ "tags": ["a", "b"], "number": 1 }), doc);
+
+        let patch = serde_json::json!([
+            { "op": "test", "path": "/number", "value": 1 },
+            { "op": "remove", "path": "/tags/0" },
+            { "op": "move", "from": "/number", "path": "/count" },
+        ]);
+        PatchFormat::Json.apply(&mut doc, &patch).unwrap();
+        assert_eq!(serde_json::json!({ "tags": ["b"], "count": 1 }), doc);
+
+        let patch = serde_json::json!([{ "op": "test", "path": "/count", "value": 2 }]);
+        assert!(matches!(
+            PatchFormat::Json.apply(&mut doc, &patch),
+            Err(ApiError::Conflict(_))
+        ));
+        let patch = serde_json::json!({ "count": 2 });
+        assert!(matches!(
+            PatchFormat::Json.apply(&mut doc, &patch),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_with_unknown_attributes() {
+        let patched = serde_json::json!({
+            "_id": "posts/1",
+            "_key": "1",
+            "title": "NU",
+            "subtitle": "dropped by the hook",
+            "legacy": { "rank": 1 },
+        });
+        let doc = serde_json::json!({ "_key": "1", "title": "RU" });
+        assert_eq!(
+            serde_json::json!({ "_key": "1", "title": "RU", "legacy": { "rank": 1 } }),
+            with_unknown_attributes(doc, &patched, &["_key", "title", "subtitle"])
+        );
+    }
+}
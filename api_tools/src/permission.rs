@@ -0,0 +1,138 @@
+use crate::ApiError;
+use actix_web::{http::header::HeaderName, web::Data, HttpRequest};
+use std::fmt;
+use std::sync::Arc;
+
+/// The operations of the derived handlers, `count_` is a `List`.
+/// `Restore` also covers listing and fetching soft deleted documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    List,
+    Fetch,
+    Create,
+    Update,
+    Replace,
+    Delete,
+    Restore,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::List => "list",
+            Operation::Fetch => "fetch",
+            Operation::Create => "create",
+            Operation::Update => "update",
+            Operation::Replace => "replace",
+            Operation::Delete => "delete",
+            Operation::Restore => "restore",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Decides whether a request has the role an operation requires,
+/// the roles are set with `#[api(permissions(list = "reader", delete = "admin"))]`.
+/// ```ignore
+/// App::new().data(Permissions::new(HeaderRoles::new("X-Roles")))
+/// ```
+pub trait PermissionChecker: Send + Sync {
+    /// An error rejects the request, usually with 401 or 403.
+    fn check(&self, req: &HttpRequest, operation: Operation, role: &str) -> Result<(), ApiError>;
+}
+
+/// The registered `PermissionChecker` of the app.
+#[derive(Clone)]
+pub struct Permissions(Arc<dyn PermissionChecker>);
+
+impl Permissions {
+    pub fn new<C: PermissionChecker + 'static>(checker: C) -> Self {
+        Permissions(Arc::new(checker))
+    }
+}
+
+/// Called by the derived handlers before the db is asked, when the operation requires a `role`.
+/// Without a registered `Permissions` the role can not be checked, the request fails.
+pub fn check_permission(
+    req: &HttpRequest,
+    operation: Operation,
+    role: &str,
+) -> Result<(), ApiError> {
+    match req.app_data::<Data<Permissions>>() {
+        Some(permissions) => permissions.0.check(req, operation, role),
+        None => Err(ApiError::Internal(format!(
+            "No PermissionChecker to check the {} role of {}.",
+            role, operation
+        ))),
+    }
+}
+
+/// Trusts a comma separated list of roles in a header set by an authenticating proxy, e.g. `X-Roles`.
+/// Requests without it are rejected with 401, ones without the role with 403.
+pub struct HeaderRoles {
+    header: HeaderName,
+}
+
+impl HeaderRoles {
+    /// Panics if `header` is not a valid header name.
+    pub fn new(header: &str) -> Self {
+        HeaderRoles {
+            header: HeaderName::from_bytes(header.as_bytes()).expect("Invalid header name"),
+        }
+    }
+}
+
+impl PermissionChecker for HeaderRoles {
+    fn check(&self, req: &HttpRequest, operation: Operation, role: &str) -> Result<(), ApiError> {
+        let roles = req
+            .headers()
+            .get(&self.header)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| ApiError::Unauthorized(format!("Missing {} header.", self.header)))?;
+        if roles.split(',').any(|granted| granted.trim() == role) {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden(format!(
+                "The {} role is required to {}.",
+                role, operation
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_permission, HeaderRoles, Operation, Permissions};
+    use crate::ApiError;
+    use actix_web::{test::TestRequest, web::Data};
+
+    #[test]
+    fn test_check_permission() {
+        let req = TestRequest::default().to_http_request();
+        assert!(matches!(
+            check_permission(&req, Operation::List, "reader"),
+            Err(ApiError::Internal(_))
+        ));
+
+        let permissions = Data::new(Permissions::new(HeaderRoles::new("X-Roles")));
+        let req = TestRequest::default()
+            .app_data(permissions.clone())
+            .to_http_request();
+        assert!(matches!(
+            check_permission(&req, Operation::List, "reader"),
+            Err(ApiError::Unauthorized(_))
+        ));
+
+        let req = TestRequest::default()
+            .header("X-Roles", "reader, editor")
+            .app_data(permissions)
+            .to_http_request();
+        assert_eq!(Ok(()), check_permission(&req, Operation::Create, "editor"));
+        assert_eq!(
+            Err(ApiError::Forbidden(
+                "The admin role is required to delete.".to_owned()
+            )),
+            check_permission(&req, Operation::Delete, "admin")
+        );
+    }
+}
@@ -13,7 +13,8 @@ mod pages;
 mod placeholder;
 mod route;
 
-use crate::actors::CreatedActor;
+use crate::actors::PostEventsActor;
+use crate::model::Post;
 use actix::Actor;
 use actix_cors::Cors;
 use actix_files as fs;
@@ -36,8 +37,12 @@ async fn main() -> std::io::Result<()> {
         arangoq::Context { app_prefix },
     );
     init::setup(&connection).await;
+    let idempotency = api_tools::IdempotencyConfig::default();
+    if let Err(err) = idempotency.setup(&connection).await {
+        log::error!("{}", err);
+    }
 
-    let cacti = CreatedActor {
+    let post_events = PostEventsActor {
         conn: connection.clone(),
     }
     .start();
@@ -49,8 +54,15 @@ async fn main() -> std::io::Result<()> {
     HttpServer::new(move || {
         App::new()
             .data(connection.clone())
-            .data(cacti.clone())
-            .wrap(Cors::new().supports_credentials().max_age(43200).finish())
+            .data(idempotency.clone())
+            .data(api_tools::EventBus::<Post>::new().subscribe(post_events.clone().recipient()))
+            .wrap(
+                Cors::new()
+                    .supports_credentials()
+                    .expose_headers(vec!["X-Total-Count", "Content-Range"])
+                    .max_age(43200)
+                    .finish(),
+            )
             .service(web::resource("/health").route(web::get().to(|| HttpResponse::Ok().finish())))
             .service(fs::Files::new("/static", "static"))
             .configure(pages::config_app)
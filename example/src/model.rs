@@ -1,5 +1,4 @@
-use crate::actors::{Created, CreatedActor};
-use api_derive::{derive_db_fields, Create, Delete, Fetch, GetAll, Replace, Update};
+use api_derive::{derive_db_fields, Create, Delete, Fetch, GetAll, Replace, Update, Upsert};
 use arangoq::*;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
@@ -26,15 +25,18 @@ use schemars::JsonSchema;
     Update,
     Replace,
     Delete,
+    Upsert,
     Validate,
 )]
 #[cfg_attr(test, derive(JsonSchema))]
+#[api(default_sort = "title")]
 pub struct Post {
     #[author]
     #[serde(default)]
     #[validate(non_control_character, email)]
     pub author: String,
 
+    #[upsert_key]
     #[validate(non_control_character, length(min = 2, max = 300))]
     pub title: String,
 
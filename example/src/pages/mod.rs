@@ -17,19 +17,15 @@ pub struct ImgOpt {
     pub gen: ImageGen,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 pub enum ImageGen {
+    #[default]
     Bricks,
     Mix,
     Noise,
     Fractal,
     Synth,
 }
-impl Default for ImageGen {
-    fn default() -> Self {
-        ImageGen::Bricks
-    }
-}
 impl ImageGen {
     pub fn generate(&self) -> String {
         match self {
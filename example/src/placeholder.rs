@@ -47,7 +47,7 @@ pub(crate) fn mix_image() -> String {
     PNGEncoder::new(&mut img_bytes)
         .encode(&img_buff, IMG_X as u32, IMG_Y as u32, ColorType::Rgb8)
         .unwrap();
-    return base64::encode(img_bytes);
+    base64::encode(img_bytes)
 }
 
 pub(crate) fn generate_random_upscale() -> String {
@@ -86,7 +86,7 @@ pub(crate) fn generate_random_upscale() -> String {
     PNGEncoder::new(&mut img_bytes)
         .encode(&img_buff, IMG_X as u32, IMG_Y as u32, ColorType::Rgb8)
         .unwrap();
-    return base64::encode(img_bytes);
+    base64::encode(img_bytes)
 }
 
 pub(crate) fn generate_random_image() -> String {
@@ -100,15 +100,15 @@ pub(crate) fn generate_random_image() -> String {
     PNGEncoder::new(&mut img_bytes)
         .encode(&img_buff, 256, 160, ColorType::Rgb8)
         .unwrap();
-    return base64::encode(img_bytes);
+    base64::encode(img_bytes)
 }
 
 pub(crate) fn generate_fractal_image() -> String {
     let imgx = 256;
     let imgy = 160;
 
-    let scalex = 3.0 as f32 / imgx as f32;
-    let scaley = 3.0 as f32 / imgy as f32;
+    let scalex = 3.0_f32 / imgx as f32;
+    let scaley = 3.0_f32 / imgy as f32;
 
     // Create a new ImgBuf with width: imgx and height: imgy
     let mut imgbuf = image::ImageBuffer::new(imgx, imgy);
@@ -145,14 +145,14 @@ pub(crate) fn generate_fractal_image() -> String {
     PNGEncoder::new(&mut img_bytes)
         .encode(&imgbuf, 256, 160, ColorType::Rgb8)
         .unwrap();
-    return base64::encode(img_bytes);
+    base64::encode(img_bytes)
 }
 
 pub(crate) fn generate_image() -> String {
     //create a new session
     if let Ok(texsynth) = ts::Session::builder()
         //load a single example image
-        .add_examples(&[
+        .add_examples([
             &"static/placeholder-1.png",
             &"static/placeholder-2.png",
             &"static/placeholder-3.png",
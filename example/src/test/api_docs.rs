@@ -1,19 +1,41 @@
 use crate::model::Post;
 use heck::CamelCase;
-use oas_gen::{ApiId, ApiPath, Oas3Builder};
+use oas_gen::{ApiId, ApiPath, Oas3Builder, QueryParamBuilder};
 use schemars::JsonSchema;
 use serde::Serialize;
+use std::collections::BTreeMap;
 
 #[derive(Serialize, JsonSchema)]
 pub struct CollectionWrapper<T> {
     collection: Vec<T>,
+    total: usize,
+    offset: usize,
+    limit: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prev: Option<String>,
 }
+/// Mirrors `api_tools::FieldError`.
+#[derive(Serialize, JsonSchema)]
+pub struct FieldErrorResponse {
+    code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    params: BTreeMap<String, serde_json::Value>,
+}
+/// Mirrors `api_tools::ErrorBody`.
 #[derive(Serialize, JsonSchema)]
 pub struct ErrorResponse {
-    #[serde(skip_serializing_if = "String::is_empty", default)]
-    err: String,
-    #[serde(skip_serializing_if = "String::is_empty", default)]
-    ok: String,
+    code: u16,
+    message: String,
+    #[serde(rename = "errorNum", skip_serializing_if = "Option::is_none")]
+    error_num: Option<u64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    details: BTreeMap<String, Vec<FieldErrorResponse>>,
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -21,7 +43,19 @@ pub(crate) fn build_public_doc() -> Oas3Builder {
     let mut oasb = Oas3Builder::default();
 
     let doc_name = "Post";
-    let query_params = vec![];
+    let query_params = vec![
+        QueryParamBuilder::new::<usize>("offset".to_owned(), Some(0))
+            .description("Number of documents to skip.".to_owned()),
+        QueryParamBuilder::new::<usize>("limit".to_owned(), Some(100))
+            .description("Maximum number of documents on a page.".to_owned()),
+        QueryParamBuilder::new::<String>("cursor".to_owned(), None)
+            .description("Opaque cursor from the next or prev link of a page.".to_owned()),
+        QueryParamBuilder::new::<String>("sort".to_owned(), Some("-title".to_owned())).description(
+            "Comma separated fields to order by, prefix with - to descend.".to_owned(),
+        ),
+        QueryParamBuilder::new::<String>("fields".to_owned(), Some("title,author".to_owned()))
+            .description("Comma separated fields to return.".to_owned()),
+    ];
     // list
     let list_path = ApiPath::with_queries(
         Some("api".to_owned()),
@@ -34,12 +68,7 @@ pub(crate) fn build_public_doc() -> Oas3Builder {
     // fetch
     let fetch_path_vec = vec![ApiId::new(doc_name.to_lowercase().as_str(), "{key}")];
     let fetch_path = ApiPath::new(Some("api".to_owned()), fetch_path_vec, None);
-    oasb.fetch_with_tests::<Post, ErrorResponse>(
-        &fetch_path,
-        doc_name.to_camel_case(),
-        None,
-        &vec![],
-    );
+    oasb.fetch_with_tests::<Post, ErrorResponse>(&fetch_path, doc_name.to_camel_case(), None, &[]);
 
     // create
     let create_path = ApiPath::new(
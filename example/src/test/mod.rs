@@ -1,37 +1,37 @@
 mod api_docs;
 
-use actix::{Actor, Context as ActorContext, Handler, Message, ResponseFuture};
 use actix_service::Service;
 use actix_web::{http::StatusCode, test, web, App};
-use api_derive::{derive_db_fields, Create, Delete, Fetch, GetAll, Replace, Update};
+use api_derive::{derive_db_fields, Create, Delete, Fetch, GetAll, Replace, Update, Upsert};
+use api_tools::{Event, EventBus, EventListener};
 use arangoq::test::TestResponse;
 use arangoq::*;
 use bytes::Bytes;
 use mockito::mock;
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
 use validator::Validate;
 
-// Set your own Created actor
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Created<T> {
-    pub data: T,
-}
+/// Keeps the names of the published events for the asserts
+#[derive(Clone, Default)]
+pub struct EventLog(Arc<Mutex<Vec<String>>>);
 
-impl<T> Message for Created<T> {
-    type Result = Result<bool, ()>;
+impl EventLog {
+    fn names(&self) -> Vec<String> {
+        self.0.lock().unwrap().clone()
+    }
 }
 
-#[derive(Clone)]
-pub struct CreatedActor {
-    pub conn: ArangoConnection,
-}
-// Keep actor implementations here
-impl Actor for CreatedActor {
-    type Context = ActorContext<Self>;
+impl<T: Serialize> EventListener<T> for EventLog {
+    fn notify(&self, event: &Event<T>) {
+        let json = serde_json::to_value(event).unwrap();
+        let name = json["event"].as_str().unwrap_or_default().to_owned();
+        self.0.lock().unwrap().push(name);
+    }
 }
 
 /// GetAll, Fetch, Create require Serialize, Deserialize, ArangoBuilder
-/// Create also requires Clone and Validate
+/// Create, Update and Replace also require Clone and Validate, Update also requires Default
 /// PartialEq required by Validate
 #[derive_db_fields(DropExtra)]
 #[derive(
@@ -58,18 +58,355 @@ pub struct TestDocument {
     #[validate(length(min = 2), non_control_character)]
     pub title: String,
     #[validate(length(min = 0), non_control_character)]
+    #[author(param = "oid")]
+    pub name: String,
+}
+
+#[actix_rt::test]
+async fn test_list() {
+    // std::env::set_var("RUST_LOG", "debug,hyper=info");
+    // let _res = env_logger::try_init();
+
+    let test_docs = vec![
+        TestDocument {
+            id: 8,
+            title: "NU".to_owned(),
+            name: "4242".to_owned(),
+            _key: "537130".to_owned(),
+            ..TestDocument::default()
+        },
+        TestDocument {
+            id: 9,
+            title: "RU".to_owned(),
+            name: "4242".to_owned(),
+            _key: "537131".to_owned(),
+            ..TestDocument::default()
+        },
+    ];
+    let mut test_resp = TestResponse::with_results(&test_docs);
+    test_resp.extra.stats.full_count = 5;
+    let mock_resp = serde_json::to_string(&test_resp).unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let _m = mock("POST", "/_db/test_db/_api/cursor")
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(mock_resp)
+        .expect(6)
+        .create();
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let mut app = test::init_service(
+        App::new()
+            .data(connection)
+            .data(api_tools::PageConfig {
+                default_limit: 2,
+                max_limit: 3,
+            })
+            .service(
+                web::resource("/parents/{oid}/testdocument")
+                    .route(web::get().to(list_testdocument)),
+            )
+            .service(web::resource("/testdocument").route(web::get().to(list_testdocument))),
+    )
+    .await;
+
+    let request = test::TestRequest::get()
+        .uri("/testdocument?offset=1&limit=2")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("X-Total-Count").unwrap(), "5");
+    assert_eq!(
+        response.headers().get("Content-Range").unwrap(),
+        "items 1-2/5"
+    );
+
+    // Check payload
+    let bdy = test::read_body(response).await;
+    log::debug!("response: {:?}", bdy);
+    let next = api_tools::PageWindow {
+        offset: 3,
+        limit: 2,
+    };
+    let prev = api_tools::PageWindow {
+        offset: 0,
+        limit: 2,
+    };
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&bdy).unwrap(),
+        serde_json::json!({
+            "collection": test_docs,
+            "total": 5,
+            "offset": 1,
+            "limit": 2,
+            "next": format!("/testdocument?cursor={}", next.encode()),
+            "prev": format!("/testdocument?cursor={}", prev.encode()),
+        })
+    );
+
+    // Follow the cursor, limit is capped by the PageConfig
+    let request = test::TestRequest::get()
+        .uri(&format!(
+            "/parents/4242/testdocument?cursor={}",
+            api_tools::PageWindow {
+                offset: 3,
+                limit: 10
+            }
+            .encode()
+        ))
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let bdy = test::read_body(response).await;
+    let page = serde_json::from_slice::<api_tools::Page<TestDocument>>(&bdy).unwrap();
+    assert_eq!((3, 3), (page.offset, page.limit));
+    assert_eq!(None, page.next);
+    assert_eq!(
+        Some(format!(
+            "/parents/4242/testdocument?cursor={}",
+            api_tools::PageWindow {
+                offset: 0,
+                limit: 3
+            }
+            .encode()
+        )),
+        page.prev
+    );
+
+    let request = test::TestRequest::get().uri("/testdocument").to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::get()
+        .uri("/testdocument?cursor=garbage")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Field filters
+    let request = test::TestRequest::get()
+        .uri("/testdocument?title=NU&number__gt=5&_key__in=537130,537131")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::get()
+        .uri("/testdocument?number__gt=five")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let bdy = test::read_body(response).await;
+    assert_eq!(
+        bdy,
+        Bytes::from_static(b"{\"code\":400,\"message\":\"Invalid value for number: five\"}")
+    );
+
+    // Sparse fieldsets
+    let request = test::TestRequest::get()
+        .uri("/testdocument?fields=title,number")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::get()
+        .uri("/testdocument?fields=title,colour")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Sorting
+    let request = test::TestRequest::get()
+        .uri("/testdocument?sort=-number,title")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::get()
+        .uri("/testdocument?sort=colour")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let bdy = test::read_body(response).await;
+    assert_eq!(
+        bdy,
+        Bytes::from_static(b"{\"code\":400,\"message\":\"Can not sort on unknown field: colour\"}")
+    );
+
+    let request = test::TestRequest::get()
+        .uri("/testdocument?colour=red")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let bdy = test::read_body(response).await;
+    assert_eq!(
+        bdy,
+        Bytes::from_static(b"{\"code\":400,\"message\":\"Unknown field colour of TestDocument\"}")
+    );
+
+    _m.assert();
+}
+
+/// Stored in `blog_categories` by default
+#[derive_db_fields(DropExtra)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, GetAll, Validate)]
+pub struct BlogCategory {
+    pub title: String,
+    #[author]
+    pub name: String,
+}
+
+#[derive_db_fields(DropExtra)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, GetAll, Validate)]
+#[api(collection = "labels", path = "blog-labels")]
+pub struct BlogTag {
+    pub title: String,
     #[author]
     pub name: String,
 }
-impl Handler<Created<TestDocument>> for CreatedActor {
-    type Result = ResponseFuture<Result<bool, ()>>;
 
-    fn handle(&mut self, msg: Created<TestDocument>, _: &mut actix::Context<Self>) -> Self::Result {
-        log::debug!("CreatedActor handle TestDocument {:#?}", msg);
-        Box::pin(futures::future::ok(false))
+/// Not ASCII before its last letter, `peñies` by default
+#[derive_db_fields(DropExtra)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, GetAll, Validate)]
+pub struct Peñy {
+    pub title: String,
+}
+
+#[actix_rt::test]
+async fn test_collection_name() {
+    let mock_resp = serde_json::to_string(&TestResponse::with_results(&[0usize])).unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let mut app = test::init_service(
+        App::new()
+            .data(connection)
+            .service(web::resource("/blog-category/count").route(web::get().to(count_blogcategory)))
+            .service(web::resource("/blog-labels/count").route(web::get().to(count_blogtag)))
+            .service(web::resource("/peny/count").route(web::get().to(count_peñy))),
+    )
+    .await;
+
+    for (uri, coll) in &[
+        ("/blog-category/count", "api_blog_categories"),
+        ("/blog-labels/count", "api_labels"),
+        ("/peny/count", "api_peñies"),
+    ] {
+        let m = mock("POST", "/_db/test_db/_api/cursor")
+            .match_body(mockito::Matcher::PartialJson(
+                serde_json::json!({ "bindVars": { "@collection": coll } }),
+            ))
+            .with_status(201)
+            .with_header("content-type", "application/json")
+            .with_body(&mock_resp)
+            .expect(1)
+            .create();
+
+        let request = test::TestRequest::get().uri(uri).to_request();
+        let response = app.call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        m.assert();
     }
 }
 
+#[actix_rt::test]
+async fn test_count() {
+    // std::env::set_var("RUST_LOG", "debug,hyper=info");
+    // let _res = env_logger::try_init();
+
+    let mock_resp = serde_json::to_string(&TestResponse::with_results(&[5usize])).unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let _m = mock("POST", "/_db/test_db/_api/cursor")
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(mock_resp)
+        .expect(2)
+        .create();
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let mut app = test::init_service(
+        App::new()
+            .data(connection)
+            .service(
+                web::resource("/parents/{oid}/testdocument/count")
+                    .route(web::get().to(count_testdocument)),
+            )
+            .service(web::resource("/testdocument/count").route(web::get().to(count_testdocument))),
+    )
+    .await;
+
+    let request = test::TestRequest::get()
+        .uri("/parents/4242/testdocument/count?number__gt=5")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("X-Total-Count").unwrap(), "5");
+
+    let bdy = test::read_body(response).await;
+    assert_eq!(bdy, Bytes::from_static(b"{\"total\":5}"));
+
+    let request = test::TestRequest::get()
+        .uri("/testdocument/count")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::get()
+        .uri("/testdocument/count?colour=red")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    _m.assert();
+}
+
 #[actix_rt::test]
 async fn test_fetch() {
     // std::env::set_var("RUST_LOG", "debug,hyper=info");
@@ -83,7 +420,8 @@ async fn test_fetch() {
         ..TestDocument::default()
     };
     let mock_resp =
-        serde_json::to_string(&TestResponse::with_results(&[test_doc.clone()])).unwrap();
+        serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(&test_doc)))
+            .unwrap();
     std::env::set_var("ARANGO_USER_NAME", "test_write");
     std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
 
@@ -91,7 +429,7 @@ async fn test_fetch() {
         .with_status(201)
         .with_header("content-type", "application/json")
         .with_body(mock_resp)
-        .expect(4)
+        .expect(5)
         .create();
 
     let connection = ArangoConnection::with_context(
@@ -172,6 +510,26 @@ async fn test_fetch() {
         )
     );
 
+    let request = test::TestRequest::get()
+        .uri("/parents/4242/testdocument/537130?fields=title")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::get()
+        .uri("/testdocument/537130?fields=colour")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let bdy = test::read_body(response).await;
+    assert_eq!(
+        bdy,
+        Bytes::from_static(b"{\"code\":400,\"message\":\"Unknown field in fields: colour\"}")
+    );
+
     _m.assert();
 }
 
@@ -189,7 +547,8 @@ async fn test_create_api() {
         ..TestDocument::default()
     };
     let mock_resp =
-        serde_json::to_string(&TestResponse::with_results(&[test_doc.clone()])).unwrap();
+        serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(&test_doc)))
+            .unwrap();
     std::env::set_var("ARANGO_USER_NAME", "test_user");
     std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
 
@@ -209,15 +568,12 @@ async fn test_create_api() {
         },
     );
 
-    let cacti = CreatedActor {
-        conn: connection.clone(),
-    }
-    .start();
+    let events = EventLog::default();
 
     let mut app = test::init_service(
         App::new()
             .data(connection)
-            .data(cacti)
+            .data(EventBus::<TestDocument>::new().subscribe(events.clone()))
             .service(
                 web::resource("/parents/{oid}/testdocument")
                     .route(web::post().to(create_testdocument)),
@@ -273,10 +629,34 @@ async fn test_create_api() {
     assert_eq!(
         bdy,
         Bytes::from_static(
-            b"{\"Err\":\"Invalid name of TestDocument: \\\"4242\\\", should be: 5500\"}"
+            b"{\"code\":400,\"message\":\"Invalid name of TestDocument: \\\"4242\\\", should be: 5500\"}"
         )
     );
 
+    // Validation errors by field
+    let request = test::TestRequest::post()
+        .uri("/testdocument")
+        .set_json(&serde_json::json!({"id": 8, "title": "N", "name": "4242"}))
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let bdy = test::read_body(response).await;
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&bdy).unwrap(),
+        serde_json::json!({
+            "code": 422,
+            "message": "Failure during validation of TestDocument: title",
+            "details": {
+                "title": [{ "code": "length", "params": { "min": 2, "value": "N" } }],
+            },
+        })
+    );
+
+    // Only the documents stored in the db are published
+    assert_eq!(events.names(), vec!["created", "created"]);
+
     _m.assert();
 }
 
@@ -294,7 +674,8 @@ async fn test_update() {
         ..TestDocument::default()
     };
     let mock_resp =
-        serde_json::to_string(&TestResponse::with_results(&[test_doc.clone()])).unwrap();
+        serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(&test_doc)))
+            .unwrap();
     std::env::set_var("ARANGO_USER_NAME", "test_user");
     std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
 
@@ -302,7 +683,7 @@ async fn test_update() {
         .with_status(201)
         .with_header("content-type", "application/json")
         .with_body(mock_resp)
-        .expect(6)
+        .expect(18)
         .create();
 
     let connection = ArangoConnection::with_context(
@@ -420,9 +801,74 @@ async fn test_update() {
         )
     );
 
-    _m.assert();
-}
-
+    // The merged document is validated
+    let request = test::TestRequest::patch()
+        .uri("/testdocument/537130")
+        .set_json(&serde_json::json!({"title":"N"}))
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let bdy = test::read_body(response).await;
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&bdy).unwrap(),
+        serde_json::json!({
+            "code": 422,
+            "message": "Failure during validation of TestDocument: title",
+            "details": {
+                "title": [{ "code": "length", "params": { "min": 2, "value": "N" } }],
+            },
+        })
+    );
+
+    // The patch can not move the document to another author
+    let request = test::TestRequest::patch()
+        .uri("/parents/4242/testdocument/537130")
+        .set_json(&serde_json::json!({"name":"5500"}))
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // RFC 6902 json patch
+    let json_patch = |ops: serde_json::Value| {
+        test::TestRequest::patch()
+            .uri("/parents/4242/testdocument/537130")
+            .header("Content-Type", "application/json-patch+json")
+            .set_payload(ops.to_string())
+            .to_request()
+    };
+    let request = json_patch(serde_json::json!([
+        { "op": "test", "path": "/title", "value": "NU" },
+        { "op": "replace", "path": "/title", "value": "RU" },
+    ]));
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = json_patch(serde_json::json!([
+        { "op": "replace", "path": "/name", "value": "5500" },
+    ]));
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let request = json_patch(serde_json::json!([
+        { "op": "test", "path": "/title", "value": "XX" },
+    ]));
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let request = test::TestRequest::patch()
+        .uri("/parents/4242/testdocument/537130")
+        .header("Content-Type", "text/plain")
+        .set_payload("title=RU")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    _m.assert();
+}
+
 #[actix_rt::test]
 async fn test_replace() {
     // std::env::set_var("RUST_LOG", "debug,hyper=info");
@@ -436,7 +882,8 @@ async fn test_replace() {
         ..TestDocument::default()
     };
     let mock_resp =
-        serde_json::to_string(&TestResponse::with_results(&[test_doc.clone()])).unwrap();
+        serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(&test_doc)))
+            .unwrap();
     std::env::set_var("ARANGO_USER_NAME", "test_user");
     std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
 
@@ -564,7 +1011,7 @@ async fn test_replace() {
     assert_eq!(
         bdy,
         Bytes::from_static(
-            b"{\"Err\":\"Invalid name of TestDocument: \\\"5500\\\", should be: 4242\"}"
+            b"{\"code\":400,\"message\":\"Invalid name of TestDocument: \\\"5500\\\", should be: 4242\"}"
         )
     );
 
@@ -584,7 +1031,8 @@ async fn test_delete() {
         ..TestDocument::default()
     };
     let mock_resp =
-        serde_json::to_string(&TestResponse::with_results(&[test_doc.clone()])).unwrap();
+        serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(&test_doc)))
+            .unwrap();
     std::env::set_var("ARANGO_USER_NAME", "test_user");
     std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
 
@@ -693,3 +1141,2014 @@ async fn test_delete() {
 
     _m.assert();
 }
+
+#[actix_rt::test]
+async fn test_not_found() {
+    // std::env::set_var("RUST_LOG", "debug,hyper=info");
+    // let _res = env_logger::try_init();
+
+    let mock_resp =
+        serde_json::to_string(&TestResponse::with_results::<TestDocument>(&[])).unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let mut app = test::init_service(
+        App::new().data(connection).service(
+            web::resource("/parents/{oid}/testdocument/{key}")
+                .route(web::get().to(fetch_testdocument))
+                .route(web::patch().to(update_testdocument))
+                .route(web::put().to(replace_testdocument))
+                .route(web::delete().to(delete_testdocument)),
+        ),
+    )
+    .await;
+
+    // No document matches the key and author
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(mock_resp)
+        .expect(4)
+        .create();
+
+    let request = test::TestRequest::get()
+        .uri("/parents/4242/testdocument/404")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let bdy = test::read_body(response).await;
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&bdy).unwrap(),
+        serde_json::json!({
+            "code": 404,
+            "message": "Document not found: test_documents/404",
+            "errorNum": 1202,
+        })
+    );
+
+    // RFC 7807 problem details on request, with the id of the request
+    let request = test::TestRequest::get()
+        .uri("/parents/4242/testdocument/404")
+        .header("Accept", "application/problem+json")
+        .header("X-Request-Id", "f00d")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let hmap = response.headers();
+    assert_eq!(
+        "application/problem+json",
+        hmap.get("content-type").unwrap()
+    );
+    assert_eq!("f00d", hmap.get("X-Request-Id").unwrap());
+
+    let bdy = test::read_body(response).await;
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&bdy).unwrap(),
+        serde_json::json!({
+            "type": "about:blank",
+            "title": "Not Found",
+            "status": 404,
+            "detail": "Document not found: test_documents/404",
+            "errorNum": 1202,
+            "requestId": "f00d",
+        })
+    );
+
+    let request = test::TestRequest::put()
+        .uri("/parents/4242/testdocument/404")
+        .set_json(&serde_json::json!({"id": 8, "title": "Watanuki", "name": ""}))
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let request = test::TestRequest::delete()
+        .uri("/parents/4242/testdocument/404")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    m.assert();
+    drop(m);
+
+    // Errors of the db are mapped by their errorNum
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .with_status(404)
+        .with_header("content-type", "application/json")
+        .with_body(
+            r#"{"error":true,"code":404,"errorNum":1202,"errorMessage":"document not found"}"#,
+        )
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::patch()
+        .uri("/parents/4242/testdocument/404")
+        .set_json(&serde_json::json!({"title": "Watanuki"}))
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    m.assert();
+    drop(m);
+
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .with_status(409)
+        .with_header("content-type", "application/json")
+        .with_body(
+            r#"{"error":true,"code":409,"errorNum":1210,"errorMessage":"unique constraint violated"}"#,
+        )
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::patch()
+        .uri("/parents/4242/testdocument/537130")
+        .set_json(&serde_json::json!({"title": "Watanuki"}))
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let bdy = test::read_body(response).await;
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&bdy).unwrap(),
+        serde_json::json!({
+            "code": 409,
+            "message": "Database Error:1210 unique constraint violated",
+            "errorNum": 1210,
+        })
+    );
+
+    m.assert();
+}
+
+#[actix_rt::test]
+async fn test_bad_body() {
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+    let mut app = test::init_service(App::new().data(connection).configure(Item::configure)).await;
+
+    // A malformed body gets the error body of the handlers, not the plain text of actix
+    let request = test::TestRequest::post()
+        .uri("/item")
+        .header("content-type", "application/json")
+        .set_payload("{\"title\": ")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let bdy: serde_json::Value = serde_json::from_slice(&test::read_body(response).await).unwrap();
+    assert_eq!(bdy["code"], 400);
+    assert!(bdy["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Bad data in request body:"));
+
+    // So does a mistyped one, as problem details on request
+    let request = test::TestRequest::post()
+        .uri("/item/bulk")
+        .header("Accept", "application/problem+json")
+        .set_json(&serde_json::json!({ "title": "NU" }))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        "application/problem+json",
+        response.headers().get("content-type").unwrap()
+    );
+    let bdy: serde_json::Value = serde_json::from_slice(&test::read_body(response).await).unwrap();
+    assert_eq!(bdy["status"], 400);
+}
+
+#[actix_rt::test]
+async fn test_etag() {
+    // std::env::set_var("RUST_LOG", "debug,hyper=info");
+    // let _res = env_logger::try_init();
+
+    let test_doc = TestDocument {
+        id: 8,
+        title: "NU".to_owned(),
+        name: "4242".to_owned(),
+        _key: "537130".to_owned(),
+        _rev: "_aaa".to_owned(),
+        ..TestDocument::default()
+    };
+    let mock_resp =
+        serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(&test_doc)))
+            .unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let mut app = test::init_service(
+        App::new().data(connection).service(
+            web::resource("/parents/{oid}/testdocument/{key}")
+                .route(web::get().to(fetch_testdocument))
+                .route(web::patch().to(update_testdocument))
+                .route(web::put().to(replace_testdocument))
+                .route(web::delete().to(delete_testdocument)),
+        ),
+    )
+    .await;
+
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(mock_resp)
+        .expect(5)
+        .create();
+
+    // The ETag is the _rev of the document
+    let request = test::TestRequest::get()
+        .uri("/parents/4242/testdocument/537130")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("ETag").unwrap(), "\"_aaa\"");
+
+    let request = test::TestRequest::get()
+        .uri("/parents/4242/testdocument/537130")
+        .header("If-None-Match", "\"_aaa\"")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers().get("ETag").unwrap(), "\"_aaa\"");
+
+    // A projection has a tag of its own, the full document doesn't answer it
+    let request = test::TestRequest::get()
+        .uri("/parents/4242/testdocument/537130?fields=title")
+        .header("If-None-Match", "\"_aaa\"")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("ETag").unwrap(), "\"_aaa;title\"");
+
+    let request = test::TestRequest::get()
+        .uri("/parents/4242/testdocument/537130?fields=title")
+        .header("If-None-Match", "\"_aaa;title\"")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    // An outdated revision is rejected before anything is written
+    let request = test::TestRequest::patch()
+        .uri("/parents/4242/testdocument/537130")
+        .header("If-Match", "\"_bbb\"")
+        .set_json(&serde_json::json!({"title": "Watanuki"}))
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+    m.assert();
+    drop(m);
+
+    // Replace and delete leave the revision check to the db
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .with_status(412)
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"error":true,"code":412,"errorNum":1200,"errorMessage":"conflict"}"#)
+        .expect(2)
+        .create();
+
+    let request = test::TestRequest::put()
+        .uri("/parents/4242/testdocument/537130")
+        .header("If-Match", "\"_bbb\"")
+        .set_json(&serde_json::json!({"id": 8, "title": "Watanuki", "name": "4242"}))
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+    let request = test::TestRequest::delete()
+        .uri("/parents/4242/testdocument/537130")
+        .header("If-Match", "\"_bbb\"")
+        .to_request();
+
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+    m.assert();
+}
+
+#[actix_rt::test]
+async fn test_configure() {
+    let mock_resp =
+        serde_json::to_string(&TestResponse::with_results::<TestDocument>(&[])).unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let _m = mock("POST", "/_db/test_db/_api/cursor")
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(mock_resp)
+        .expect(4)
+        .create();
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let mut app = test::init_service(
+        App::new()
+            .data(connection)
+            .configure(TestDocument::configure)
+            .configure(|cfg| BlogTag::configure_with_prefix(cfg, "/api/")),
+    )
+    .await;
+
+    let request = test::TestRequest::get().uri("/test-document").to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::get()
+        .uri("/4242/test-document")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The handler responds 404, not the router
+    let request = test::TestRequest::delete()
+        .uri("/4242/test-document/404")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let bdy = test::read_body(response).await;
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&bdy).unwrap()["message"],
+        "Document not found: test_documents/404"
+    );
+
+    let request = test::TestRequest::get()
+        .uri("/api/4242/blog-labels")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Only the derived operations are routed
+    let request = test::TestRequest::get()
+        .uri("/api/blog-labels/42")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(test::read_body(response).await.is_empty());
+
+    // A document at /test-document/count couldn't be fetched, the key is rejected
+    let request = test::TestRequest::post()
+        .uri("/test-document")
+        .set_json(&TestDocument {
+            title: "NU".to_owned(),
+            _key: "count".to_owned(),
+            ..TestDocument::default()
+        })
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    _m.assert();
+}
+
+/// Without an `#[author]` only the unscoped handlers are routed
+#[derive_db_fields(DropExtra)]
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Serialize,
+    Deserialize,
+    ArangoBuilder,
+    GetAll,
+    Fetch,
+    Validate,
+    Update,
+    Replace,
+    Delete,
+)]
+pub struct Country {
+    #[validate(length(min = 2))]
+    pub name: String,
+}
+
+#[actix_rt::test]
+async fn test_without_author() {
+    let country = Country {
+        name: "Hungary".to_owned(),
+        _key: "hu".to_owned(),
+        ..Country::default()
+    };
+    let mock_resp =
+        serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(&country))).unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let _m = mock("POST", "/_db/test_db/_api/cursor")
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(mock_resp)
+        .expect(6)
+        .create();
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let events = EventLog::default();
+
+    let mut app = test::init_service(
+        App::new()
+            .data(connection)
+            .data(EventBus::<Country>::new().subscribe(events.clone()))
+            .configure(Country::configure),
+    )
+    .await;
+
+    let request = test::TestRequest::get().uri("/country").to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::get().uri("/country/hu").to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::patch()
+        .uri("/country/hu")
+        .set_json(&serde_json::json!({"name": "Magyarország"}))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::put()
+        .uri("/country/hu")
+        .set_json(&country)
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::delete().uri("/country/hu").to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_eq!(events.names(), vec!["updated", "replaced", "deleted"]);
+
+    let request = test::TestRequest::get().uri("/hu/country").to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    _m.assert();
+}
+
+/// Scoped by both organisation and user, read from the path by name
+#[derive_db_fields(DropExtra)]
+#[derive(
+    Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, Fetch, Validate, Replace,
+)]
+pub struct Membership {
+    #[author]
+    pub org: String,
+    #[author(param = "uid")]
+    pub user: String,
+    pub role: String,
+}
+
+#[actix_rt::test]
+async fn test_author_params() {
+    let membership = Membership {
+        org: "acme".to_owned(),
+        user: "u42".to_owned(),
+        role: "admin".to_owned(),
+        _key: "m1".to_owned(),
+        ..Membership::default()
+    };
+    let mock_resp = serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(
+        &membership,
+    )))
+    .unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let mut app = test::init_service(
+        App::new().data(connection).service(
+            web::resource("/orgs/{org}/users/{uid}/membership/{key}")
+                .route(web::get().to(fetch_membership))
+                .route(web::put().to(replace_membership)),
+        ),
+    )
+    .await;
+
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::Regex(r"item\.org == @filterVar\d+".to_owned()),
+            mockito::Matcher::Regex(r"item\.user == @filterVar\d+".to_owned()),
+            mockito::Matcher::Regex(r#""acme".*"u42""#.to_owned()),
+        ]))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(&mock_resp)
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::get()
+        .uri("/orgs/acme/users/u42/membership/m1")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    m.assert();
+    drop(m);
+
+    // The authors on the path are filled in, other ones are rejected
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+            "bindVars": { "doc": { "org": "acme", "user": "u42" } }
+        })))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(&mock_resp)
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::put()
+        .uri("/orgs/acme/users/u42/membership/m1")
+        .set_json(&serde_json::json!({"org": "", "user": "", "role": "admin"}))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::put()
+        .uri("/orgs/acme/users/u42/membership/m1")
+        .set_json(&serde_json::json!({"org": "acme", "user": "u7", "role": "admin"}))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    m.assert();
+}
+
+#[actix_rt::test]
+async fn test_author_resolver() {
+    let test_doc = TestDocument {
+        id: 8,
+        title: "NU".to_owned(),
+        name: "4242".to_owned(),
+        _key: "537130".to_owned(),
+        ..TestDocument::default()
+    };
+    let mock_resp =
+        serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(&test_doc)))
+            .unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let mut app = test::init_service(
+        App::new()
+            .data(connection)
+            .data(api_tools::Authors::new(api_tools::HeaderAuthor::new(
+                "X-User-Id",
+            )))
+            .service(
+                web::resource("/parents/{oid}/testdocument/{key}")
+                    .route(web::get().to(fetch_testdocument)),
+            )
+            .service(web::resource("/testdocument/{key}").route(web::get().to(fetch_testdocument))),
+    )
+    .await;
+
+    // Rejected before the db is asked
+    let request = test::TestRequest::get()
+        .uri("/parents/4242/testdocument/537130")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = test::TestRequest::get()
+        .uri("/parents/4242/testdocument/537130")
+        .header("X-User-Id", "5500")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // The unscoped route is scoped to the authenticated author
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::Regex(r"item\.name == @filterVar\d+".to_owned()),
+            mockito::Matcher::Regex(r#""4242""#.to_owned()),
+        ]))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(&mock_resp)
+        .expect(2)
+        .create();
+
+    let request = test::TestRequest::get()
+        .uri("/parents/4242/testdocument/537130")
+        .header("X-User-Id", "4242")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::get()
+        .uri("/testdocument/537130")
+        .header("X-User-Id", "4242")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    m.assert();
+}
+
+#[derive_db_fields(DropExtra)]
+#[derive(
+    Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, GetAll, Create, Delete, Validate,
+)]
+#[api(permissions(list = "reader", create = "admin", delete = "admin"))]
+pub struct Secret {
+    pub value: String,
+}
+
+#[actix_rt::test]
+async fn test_permissions() {
+    let mock_resp = serde_json::to_string(&TestResponse::with_results::<Secret>(&[])).unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    // Nothing can check the roles
+    let mut app = test::init_service(
+        App::new()
+            .data(connection.clone())
+            .configure(Secret::configure),
+    )
+    .await;
+
+    let request = test::TestRequest::get().uri("/secret").to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let mut app = test::init_service(
+        App::new()
+            .data(connection)
+            .data(api_tools::Permissions::new(api_tools::HeaderRoles::new(
+                "X-Roles",
+            )))
+            .data(api_tools::IdempotencyConfig::default())
+            .configure(Secret::configure),
+    )
+    .await;
+
+    let request = test::TestRequest::get().uri("/secret").to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Checked before the idempotency key is reserved
+    let reserve = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex(
+            r"UPSERT \{ _key: @key \}".to_owned(),
+        ))
+        .expect(0)
+        .create();
+    let request = test::TestRequest::post()
+        .uri("/secret")
+        .header("X-Roles", "reader")
+        .header("Idempotency-Key", "retry-me")
+        .set_json(&serde_json::json!({ "value": "42" }))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    reserve.assert();
+    drop(reserve);
+
+    let request = test::TestRequest::delete()
+        .uri("/secret/42")
+        .header("X-Roles", "reader")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(&mock_resp)
+        .expect(2)
+        .create();
+
+    let request = test::TestRequest::get()
+        .uri("/secret")
+        .header("X-Roles", "reader")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::get()
+        .uri("/secret/count")
+        .header("X-Roles", "reader")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    m.assert();
+}
+
+/// Sets its slug before it is stored, the `locked` one can not be deleted,
+/// announcing the `offline` one fails after it was stored
+#[derive_db_fields(DropExtra)]
+#[derive(
+    Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, Create, Delete, Validate,
+)]
+#[api(hooks)]
+pub struct Article {
+    pub title: String,
+    #[validate(length(min = 1))]
+    #[serde(default)]
+    pub slug: String,
+}
+
+#[api_tools::async_trait(?Send)]
+impl api_tools::CrudHooks for Article {
+    async fn before_create(
+        &mut self,
+        _req: &actix_web::HttpRequest,
+    ) -> Result<(), api_tools::ApiError> {
+        self.slug = self.title.to_lowercase().replace(' ', "-");
+        Ok(())
+    }
+
+    async fn after_create(&self, _req: &actix_web::HttpRequest) -> Result<(), api_tools::ApiError> {
+        if self.slug == "offline" {
+            return Err(api_tools::ApiError::Internal(
+                "Can not announce article offline.".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn before_delete(
+        key: &str,
+        _req: &actix_web::HttpRequest,
+    ) -> Result<(), api_tools::ApiError> {
+        if key == "locked" {
+            return Err(api_tools::ApiError::Forbidden(format!(
+                "Article {} is locked.",
+                key
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[actix_rt::test]
+async fn test_hooks() {
+    let article = Article {
+        title: "Hello World".to_owned(),
+        slug: "hello-world".to_owned(),
+        _key: "a1".to_owned(),
+        ..Article::default()
+    };
+    let mock_resp =
+        serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(&article))).unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let _m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex("hello-world".to_owned()))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(mock_resp)
+        .expect(1)
+        .create();
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let events = EventLog::default();
+    let mut app = test::init_service(
+        App::new()
+            .data(connection)
+            .data(EventBus::<Article>::new().subscribe(events.clone()))
+            .configure(Article::configure),
+    )
+    .await;
+
+    // The slug is set before the validation
+    let request = test::TestRequest::post()
+        .uri("/article")
+        .set_json(&serde_json::json!({"title": "Hello World"}))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = test::TestRequest::delete()
+        .uri("/article/locked")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    _m.assert();
+
+    // A failing after hook is the response, the stored article is published anyway
+    let offline = Article {
+        title: "Offline".to_owned(),
+        slug: "offline".to_owned(),
+        _key: "a2".to_owned(),
+        ..Article::default()
+    };
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex("offline".to_owned()))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::to_string(&TestResponse::with_results(&[offline])).unwrap())
+        .expect(1)
+        .create();
+    let request = test::TestRequest::post()
+        .uri("/article")
+        .set_json(&serde_json::json!({"title": "Offline"}))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    m.assert();
+    assert_eq!(events.names(), vec!["created", "created"]);
+}
+
+#[derive_db_fields(DropExtra, Timestamps)]
+#[derive(
+    Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, Create, Validate, Update,
+)]
+pub struct Note {
+    pub text: String,
+}
+
+#[actix_rt::test]
+async fn test_timestamps() {
+    let note = Note {
+        text: "NU".to_owned(),
+        _key: "n1".to_owned(),
+        created_at: "2020-07-20T09:30:00.000Z".to_owned(),
+        updated_at: "2020-07-20T09:30:00.000Z".to_owned(),
+        ..Note::default()
+    };
+    let mock_resp =
+        serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(&note))).unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let mut app = test::init_service(App::new().data(connection).configure(Note::configure)).await;
+
+    // Set by the server, whatever the client sent
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::Regex(r#""created_at":"2\d{3}-"#.to_owned()),
+            mockito::Matcher::Regex(r#""updated_at":"2\d{3}-"#.to_owned()),
+        ]))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(&mock_resp)
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::post()
+        .uri("/note")
+        .set_json(&serde_json::json!({
+            "text": "NU",
+            "created_at": "1999-01-01T00:00:00.000Z",
+            "updated_at": "1999-01-01T00:00:00.000Z",
+        }))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    m.assert();
+    drop(m);
+
+    // The stored created_at is kept by the db
+    let load = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex("LIMIT 1".to_owned()))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(&mock_resp)
+        .expect(1)
+        .create();
+    let replace = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::Regex(r"MERGE\(@doc, KEEP\(item, @preserve\)\)".to_owned()),
+            mockito::Matcher::Regex(r#""updated_at":"2\d{3}-"#.to_owned()),
+        ]))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(&mock_resp)
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::patch()
+        .uri("/note/n1")
+        .set_json(&serde_json::json!({"created_at": "1999-01-01T00:00:00.000Z"}))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    load.assert();
+    replace.assert();
+}
+
+#[derive_db_fields(DropExtra, SoftDelete)]
+#[derive(
+    Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, GetAll, Fetch, Delete, Validate,
+)]
+#[api(permissions(restore = "admin"))]
+pub struct Draft {
+    pub text: String,
+}
+
+/// Soft deleted without a restore role, nobody may list or restore the deleted ones
+#[derive_db_fields(DropExtra, Timestamps, SoftDelete)]
+#[derive(
+    Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, GetAll, Delete, Validate,
+)]
+pub struct Memo {
+    pub text: String,
+}
+
+#[actix_rt::test]
+async fn test_soft_delete() {
+    let draft = Draft {
+        text: "NU".to_owned(),
+        _key: "d1".to_owned(),
+        ..Draft::default()
+    };
+    let mock_resp =
+        serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(&draft))).unwrap();
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let events = EventLog::default();
+    let mut app = test::init_service(
+        App::new()
+            .data(connection)
+            .data(api_tools::Permissions::new(api_tools::HeaderRoles::new(
+                "X-Roles",
+            )))
+            .data(EventBus::<Draft>::new().subscribe(events.clone()))
+            .configure(Draft::configure)
+            .configure(Memo::configure),
+    )
+    .await;
+
+    // Marked, not removed
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::Regex(r"FILTER !item.deleted_at UPDATE item WITH @patch".to_owned()),
+            mockito::Matcher::Regex(r#""patch":\{"deleted_at":"2\d{3}-"#.to_owned()),
+        ]))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(&mock_resp)
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::delete().uri("/draft/d1").to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    m.assert();
+    drop(m);
+
+    // Deleted ones are skipped
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex(
+            "FILTER !item.deleted_at".to_owned(),
+        ))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(&mock_resp)
+        .expect(2)
+        .create();
+
+    for uri in &["/draft", "/draft/d1"] {
+        let request = test::TestRequest::get().uri(uri).to_request();
+        let response = app.call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    m.assert();
+    drop(m);
+
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex(
+            "FILTER !item.deleted_at COLLECT".to_owned(),
+        ))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::to_string(&TestResponse::with_results(&[1usize])).unwrap())
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::get().uri("/draft/count").to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    m.assert();
+    drop(m);
+
+    // Only for those who may restore them
+    let request = test::TestRequest::get()
+        .uri("/draft?include_deleted=true")
+        .header("X-Roles", "reader")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let request = test::TestRequest::get()
+        .uri("/memo?include_deleted=true")
+        .header("X-Roles", "admin")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let request = test::TestRequest::post()
+        .uri("/draft/d1/restore")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = test::TestRequest::post()
+        .uri("/memo/m1/restore")
+        .header("X-Roles", "admin")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // A deletion is a change, stamped like the others
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex(
+            r#""patch":\{"deleted_at":"(2\d{3}-[^"]+)","updated_at":"2\d{3}-"#.to_owned(),
+        ))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::to_string(&TestResponse::with_results(&[serde_json::json!({
+                "_key": "m1",
+                "text": "NU",
+            })]))
+            .unwrap(),
+        )
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::delete().uri("/memo/m1").to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    m.assert();
+    drop(m);
+
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex(
+            r#""query":"FOR item IN @@collection SORT"#.to_owned(),
+        ))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(&mock_resp)
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::get()
+        .uri("/draft?include_deleted=true")
+        .header("X-Roles", "admin")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    m.assert();
+    drop(m);
+
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::Regex(r"FILTER item.deleted_at UPDATE".to_owned()),
+            mockito::Matcher::PartialJson(serde_json::json!({
+                "bindVars": { "patch": { "deleted_at": null } },
+            })),
+        ]))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(&mock_resp)
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::post()
+        .uri("/draft/d1/restore")
+        .header("X-Roles", "admin")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    m.assert();
+    drop(m);
+
+    // A live document isn't restored, the db finds no deleted one
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex(
+            r"FILTER item.deleted_at UPDATE".to_owned(),
+        ))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::to_string(&TestResponse::with_results::<Draft>(&[])).unwrap())
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::post()
+        .uri("/draft/d2/restore")
+        .header("X-Roles", "admin")
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    m.assert();
+    assert_eq!(events.names(), vec!["deleted", "restored"]);
+}
+
+#[derive_db_fields(DropExtra)]
+#[derive(
+    Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, Create, Update, Delete, Validate,
+)]
+pub struct Item {
+    #[validate(length(min = 2))]
+    pub title: String,
+}
+
+#[actix_rt::test]
+async fn test_bulk() {
+    let item = |key: &str, title: &str| Item {
+        title: title.to_owned(),
+        _key: key.to_owned(),
+        _rev: format!("_{}", key),
+        ..Item::default()
+    };
+    let with_results =
+        |items: &[Item]| serde_json::to_string(&TestResponse::with_results(items)).unwrap();
+    // the multi-document api of ArangoDB responds with the result of each document
+    let documents = "/_db/test_db/_api/document/api_items";
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let mut app = test::init_service(App::new().data(connection).configure(Item::configure)).await;
+
+    // Only the valid ones are inserted, in one request
+    let m = mock("POST", documents)
+        .match_query(mockito::Matcher::UrlEncoded(
+            "returnNew".to_owned(),
+            "true".to_owned(),
+        ))
+        .match_body(mockito::Matcher::Json(serde_json::json!([
+            { "title": "AA" },
+            { "title": "CC" },
+        ])))
+        .with_status(202)
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!([{ "new": item("a", "AA") }, { "new": item("c", "CC") }]).to_string(),
+        )
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::post()
+        .uri("/item/bulk")
+        .set_json(&serde_json::json!([
+            { "title": "AA" },
+            { "title": "B" },
+            { "title": "CC" },
+            "DD",
+        ]))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+    let bdy: serde_json::Value = serde_json::from_slice(&test::read_body(response).await).unwrap();
+    assert_eq!(bdy["succeeded"], 2);
+    assert_eq!(bdy["failed"], 2);
+    let statuses = |bdy: &serde_json::Value| {
+        bdy["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["status"].as_u64().unwrap())
+            .collect::<Vec<u64>>()
+    };
+    assert_eq!(statuses(&bdy), vec![201, 422, 201, 400]);
+    assert_eq!(bdy["results"][2]["data"]["_key"], "c");
+
+    m.assert();
+    drop(m);
+
+    // A duplicate fails on its own, the others are inserted
+    let m = mock("POST", documents)
+        .match_query(mockito::Matcher::Any)
+        .with_status(202)
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!([
+                { "new": item("d", "AA") },
+                { "error": true, "errorNum": 1210, "errorMessage": "unique constraint violated" },
+                { "new": item("e", "CC") },
+            ])
+            .to_string(),
+        )
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::post()
+        .uri("/item/bulk")
+        .set_json(&serde_json::json!([
+            { "_key": "d", "title": "AA" },
+            { "_key": "d", "title": "BB" },
+            { "_key": "e", "title": "CC" },
+        ]))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+    let bdy: serde_json::Value = serde_json::from_slice(&test::read_body(response).await).unwrap();
+    assert_eq!(statuses(&bdy), vec![201, 409, 201]);
+    assert_eq!(bdy["results"][1]["error"]["errorNum"], 1210);
+    assert_eq!(bdy["succeeded"], 2);
+
+    m.assert();
+    drop(m);
+
+    // Loaded together, and replaced at the loaded revisions
+    let load = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+            "query": "FOR item IN @@collection FILTER item._key IN @filterVar1 RETURN item",
+            "bindVars": { "filterVar1": ["a", "x", "c"] },
+        })))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(with_results(&[item("a", "AA"), item("c", "CC")]))
+        .expect(1)
+        .create();
+    let replace = mock("PUT", documents)
+        .match_query(mockito::Matcher::UrlEncoded(
+            "ignoreRevs".to_owned(),
+            "false".to_owned(),
+        ))
+        .match_body(mockito::Matcher::Json(serde_json::json!([
+            { "_key": "a", "_rev": "_a", "title": "NU" },
+        ])))
+        .with_status(202)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::json!([{ "new": item("a", "NU") }]).to_string())
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::patch()
+        .uri("/item/bulk")
+        .set_json(&serde_json::json!([
+            { "_key": "a", "title": "NU" },
+            { "_key": "x", "title": "NU" },
+            { "_key": "c", "_rev": "_old", "title": "NU" },
+            { "title": "NU" },
+        ]))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+    let bdy: serde_json::Value = serde_json::from_slice(&test::read_body(response).await).unwrap();
+    assert_eq!(statuses(&bdy), vec![200, 404, 412, 400]);
+    assert_eq!(bdy["results"][0]["data"]["title"], "NU");
+
+    load.assert();
+    replace.assert();
+    drop(load);
+    drop(replace);
+
+    let load = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+            "bindVars": { "filterVar1": ["a", "x"] },
+        })))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(with_results(&[item("a", "AA")]))
+        .expect(1)
+        .create();
+    let remove = mock("DELETE", documents)
+        .match_query(mockito::Matcher::Any)
+        .match_body(mockito::Matcher::Json(serde_json::json!([
+            { "_key": "a", "_rev": "_a" },
+        ])))
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::json!([{ "old": item("a", "AA") }]).to_string())
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::delete()
+        .uri("/item/bulk")
+        .set_json(&serde_json::json!(["a", "x"]))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+    let bdy: serde_json::Value = serde_json::from_slice(&test::read_body(response).await).unwrap();
+    assert_eq!(statuses(&bdy), vec![200, 404]);
+
+    load.assert();
+    remove.assert();
+    drop(load);
+    drop(remove);
+
+    let request = test::TestRequest::delete()
+        .uri("/item/bulk")
+        .set_json(&serde_json::json!([]))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn test_update_keeps_unknown_attributes() {
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+    let mut app = test::init_service(App::new().data(connection).configure(Item::configure)).await;
+
+    // Item drops extra attributes, but those stored by others are kept on a patch
+    let stored = serde_json::json!({ "_key": "k", "_rev": "_k", "title": "AA", "legacy": "x" });
+    let load = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex("RETURN item".to_owned()))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::to_string(&TestResponse::with_results(&[stored])).unwrap())
+        .expect(1)
+        .create();
+    let replaced = serde_json::json!({ "_key": "k", "_rev": "_l", "title": "NU", "legacy": "x" });
+    let replace = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+            "bindVars": { "doc": { "_key": "k", "title": "NU", "legacy": "x" } },
+        })))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::to_string(&TestResponse::with_results(&[replaced])).unwrap())
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::patch()
+        .uri("/item/k")
+        .set_json(&serde_json::json!({ "title": "NU" }))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bdy: serde_json::Value = serde_json::from_slice(&test::read_body(response).await).unwrap();
+    assert_eq!(bdy["title"], "NU");
+    assert_eq!(bdy.get("legacy"), None);
+
+    load.assert();
+    replace.assert();
+}
+
+#[derive_db_fields(DropExtra, Timestamps)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, Upsert, Validate)]
+pub struct Label {
+    #[upsert_key]
+    pub name: String,
+    #[author]
+    #[serde(default)]
+    pub owner: String,
+    pub color: String,
+}
+
+#[actix_rt::test]
+async fn test_upsert() {
+    let label = Label {
+        name: "todo".to_owned(),
+        owner: "o1".to_owned(),
+        color: "red".to_owned(),
+        _key: "l1".to_owned(),
+        _rev: "_l1".to_owned(),
+        ..Label::default()
+    };
+    let upserted = |created: bool| {
+        serde_json::to_string(&TestResponse::with_results(&[api_tools::Upserted {
+            doc: label.clone(),
+            created,
+        }]))
+        .unwrap()
+    };
+    // the lookup of the stored one, which decides the hooks
+    let lookup = |found: &[Label]| {
+        mock("POST", "/_db/test_db/_api/cursor")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::Regex(
+                    r"FILTER item.name == @filterVar1 FILTER item.owner == @filterVar2 LIMIT 1"
+                        .to_owned(),
+                ),
+                mockito::Matcher::PartialJson(serde_json::json!({
+                    "bindVars": { "filterVar1": "todo", "filterVar2": "o1" },
+                })),
+            ]))
+            .with_status(201)
+            .with_header("content-type", "application/json")
+            .with_body(serde_json::to_string(&TestResponse::with_results(found)).unwrap())
+            .expect(1)
+            .create()
+    };
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let events = EventLog::default();
+    let mut app = test::init_service(
+        App::new()
+            .data(connection)
+            .data(EventBus::<Label>::new().subscribe(events.clone()))
+            .configure(Label::configure),
+    )
+    .await;
+
+    let found = lookup(&[]);
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::Regex(
+                r"UPSERT \{ name: @searchVar0, owner: @searchVar1 \} INSERT @insert REPLACE MERGE\(@replace, KEEP\(OLD, @preserve\)\)".to_owned(),
+            ),
+            mockito::Matcher::PartialJson(serde_json::json!({
+                "bindVars": { "searchVar0": "todo", "searchVar1": "o1" },
+            })),
+            mockito::Matcher::Regex(r#""insert":\{[^}]*"created_at":"2\d{3}-"#.to_owned()),
+        ]))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(upserted(true))
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::put()
+        .uri("/o1/label")
+        .set_json(&serde_json::json!({ "name": "todo", "color": "red" }))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!("\"_l1\"", response.headers().get("ETag").unwrap());
+
+    found.assert();
+    m.assert();
+    drop(found);
+    drop(m);
+
+    let found = lookup(std::slice::from_ref(&label));
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex(r"UPSERT".to_owned()))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(upserted(false))
+        .expect(1)
+        .create();
+
+    let request = test::TestRequest::put()
+        .uri("/o1/label")
+        .set_json(&serde_json::json!({ "name": "todo", "color": "blue" }))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    found.assert();
+    m.assert();
+
+    // Validated like a new one
+    let request = test::TestRequest::put()
+        .uri("/o1/label")
+        .set_json(&serde_json::json!({ "name": "todo", "owner": "o2", "color": "blue" }))
+        .to_request();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(events.names(), vec!["created", "replaced"]);
+}
+
+/// Soft deleted, its hooks note whether it was inserted or replaced, a new one gets a uuid
+#[derive_db_fields(DropExtra, SoftDelete)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, Upsert, Validate)]
+#[api(hooks, key = "uuid")]
+pub struct Keyword {
+    #[upsert_key]
+    pub name: String,
+    #[serde(default)]
+    pub origin: String,
+}
+
+#[api_tools::async_trait(?Send)]
+impl api_tools::CrudHooks for Keyword {
+    async fn before_create(
+        &mut self,
+        _req: &actix_web::HttpRequest,
+    ) -> Result<(), api_tools::ApiError> {
+        self.origin = "created".to_owned();
+        Ok(())
+    }
+
+    async fn before_replace(
+        &mut self,
+        _req: &actix_web::HttpRequest,
+    ) -> Result<(), api_tools::ApiError> {
+        self.origin = "replaced".to_owned();
+        Ok(())
+    }
+}
+
+#[actix_rt::test]
+async fn test_upsert_hooks() {
+    let keyword = |origin: &str| Keyword {
+        name: "rust".to_owned(),
+        origin: origin.to_owned(),
+        _key: "t1".to_owned(),
+        _rev: "_t1".to_owned(),
+        ..Keyword::default()
+    };
+    let upserted = |created: bool, origin: &str| {
+        serde_json::to_string(&TestResponse::with_results(&[api_tools::Upserted {
+            doc: keyword(origin),
+            created,
+        }]))
+        .unwrap()
+    };
+    // the soft deleted ones are not found, so not replaced
+    let lookup = |found: &[Keyword]| {
+        mock("POST", "/_db/test_db/_api/cursor")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::Regex(
+                    r"FILTER item.name == @filterVar1 FILTER item.deleted_at == @filterVar2 LIMIT 1"
+                        .to_owned(),
+                ),
+                mockito::Matcher::PartialJson(serde_json::json!({
+                    "bindVars": { "filterVar1": "rust", "filterVar2": null },
+                })),
+            ]))
+            .with_status(201)
+            .with_header("content-type", "application/json")
+            .with_body(serde_json::to_string(&TestResponse::with_results(found)).unwrap())
+            .expect(1)
+            .create()
+    };
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+    let mut app =
+        test::init_service(App::new().data(connection).configure(Keyword::configure)).await;
+    let put = || {
+        test::TestRequest::put()
+            .uri("/keyword")
+            .set_json(&serde_json::json!({ "name": "rust" }))
+            .to_request()
+    };
+
+    // None yet, only the hooks of a create run, with its key
+    let found = lookup(&[]);
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::Regex(
+                r"UPSERT \{ name: @searchVar0, deleted_at: @searchVar1 \} INSERT".to_owned(),
+            ),
+            mockito::Matcher::PartialJson(serde_json::json!({
+                "bindVars": {
+                    "searchVar0": "rust",
+                    "searchVar1": null,
+                    "insert": { "origin": "created" },
+                    "replace": { "origin": "created" },
+                },
+            })),
+            mockito::Matcher::Regex(r#""insert":\{[^}]*"_key":"[0-9a-f-]{36}""#.to_owned()),
+        ]))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(upserted(true, "created"))
+        .expect(1)
+        .create();
+
+    let response = app.call(put()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    found.assert();
+    m.assert();
+    drop(found);
+    drop(m);
+
+    // A stored one, only the hooks of a replace run
+    let found = lookup(&[keyword("created")]);
+    let m = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::Regex(r"UPSERT".to_owned()),
+            mockito::Matcher::PartialJson(serde_json::json!({
+                "bindVars": {
+                    "insert": { "origin": "replaced" },
+                    "replace": { "origin": "replaced" },
+                },
+            })),
+        ]))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(upserted(false, "replaced"))
+        .expect(1)
+        .create();
+
+    let response = app.call(put()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    found.assert();
+    m.assert();
+}
+
+#[actix_rt::test]
+async fn test_idempotency() {
+    let item = Item {
+        title: "AA".to_owned(),
+        _key: "a".to_owned(),
+        _rev: "_a".to_owned(),
+        ..Item::default()
+    };
+    let fingerprint = |title: &str| {
+        api_tools::fingerprint(&serde_json::json!(Item {
+            title: title.to_owned(),
+            ..Item::default()
+        }))
+    };
+    let reservation = |reserved: bool, title: &str, status: u16| {
+        serde_json::to_string(&TestResponse::with_results(&[api_tools::Reservation {
+            record: api_tools::IdempotencyRecord {
+                _key: "k".to_owned(),
+                fingerprint: fingerprint(title),
+                expires_at: "2100-01-01T00:00:00.000Z".to_owned(),
+                status,
+                body: if status == 0 {
+                    serde_json::Value::Null
+                } else {
+                    serde_json::json!(item)
+                },
+            },
+            reserved,
+        }]))
+        .unwrap()
+    };
+    let reserve = |body: String| {
+        mock("POST", "/_db/test_db/_api/cursor")
+            .match_body(mockito::Matcher::AllOf(vec![
+                // an expired lease is taken over
+                mockito::Matcher::Regex(
+                    r"UPSERT \{ _key: @key \} INSERT @record UPDATE \(OLD.expires_at <= @now \? @record".to_owned(),
+                ),
+                mockito::Matcher::PartialJson(serde_json::json!({
+                    "bindVars": { "@collection": "api_idempotency_keys" },
+                })),
+            ]))
+            .with_status(201)
+            .with_header("content-type", "application/json")
+            .with_body(body)
+    };
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let mut app = test::init_service(
+        App::new()
+            .data(connection)
+            .data(api_tools::IdempotencyConfig::default())
+            .configure(Item::configure),
+    )
+    .await;
+    let post = |title: &str| {
+        test::TestRequest::post()
+            .uri("/item")
+            .header("Idempotency-Key", "retry-me")
+            .set_json(&serde_json::json!({ "title": title }))
+            .to_request()
+    };
+
+    // The first request is handled and its response stored
+    let m = reserve(reservation(true, "AA", 0)).expect(1).create();
+    let create = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex(
+            r#""@collection":"api_items""#.to_owned(),
+        ))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::to_string(&TestResponse::with_results(std::slice::from_ref(&item)))
+                .unwrap(),
+        )
+        .expect(1)
+        .create();
+    let store = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::Regex(
+                r"UPDATE \{ _key: @key \} WITH \{ status: @status, body: @body, expires_at: @expires_at \}".to_owned(),
+            ),
+            mockito::Matcher::PartialJson(serde_json::json!({
+                "bindVars": { "status": 200, "body": { "title": "AA", "_key": "a" } },
+            })),
+        ]))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::to_string(&TestResponse::with_results::<serde_json::Value>(&[])).unwrap(),
+        )
+        .expect(1)
+        .create();
+
+    let response = app.call(post("AA")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("Idempotent-Replayed").is_none());
+
+    m.assert();
+    store.assert();
+    drop(m);
+
+    // A retry gets the stored response, without creating another one
+    let m = reserve(reservation(false, "AA", 200)).expect(1).create();
+    let response = app.call(post("AA")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        "true",
+        response.headers().get("Idempotent-Replayed").unwrap()
+    );
+    let body: Item = serde_json::from_slice(&test::read_body(response).await).unwrap();
+    assert_eq!("a", body._key);
+
+    m.assert();
+    create.assert();
+    drop(m);
+
+    // The same key with another body
+    let m = reserve(reservation(false, "BB", 200)).expect(1).create();
+    let response = app.call(post("AA")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    m.assert();
+    drop(m);
+
+    // The first request is still handled
+    let m = reserve(reservation(false, "AA", 0)).expect(1).create();
+    let response = app.call(post("AA")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    m.assert();
+    drop(m);
+
+    // A failed request releases the key
+    let m = reserve(reservation(true, "B", 0)).expect(1).create();
+    let release = mock("POST", "/_db/test_db/_api/cursor")
+        .match_body(mockito::Matcher::Regex(
+            r"REMOVE \{ _key: @key \}".to_owned(),
+        ))
+        .with_status(201)
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::to_string(&TestResponse::with_results::<serde_json::Value>(&[])).unwrap(),
+        )
+        .expect(1)
+        .create();
+    let response = app.call(post("B")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    m.assert();
+    release.assert();
+}
+
+#[derive_db_fields(DropExtra)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, Create, Validate)]
+#[api(key = "slug(title)")]
+pub struct Page {
+    pub title: String,
+}
+
+#[derive_db_fields(DropExtra)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, Create, Validate)]
+#[api(key = "client")]
+pub struct Topic {
+    pub name: String,
+}
+
+#[derive_db_fields(DropExtra)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ArangoBuilder, Create, Validate)]
+#[api(key = "ulid")]
+pub struct Ticket {
+    pub subject: String,
+}
+
+#[actix_rt::test]
+async fn test_keys() {
+    let inserted = |key_pattern: &str| {
+        mock("POST", "/_db/test_db/_api/cursor")
+            .match_body(mockito::Matcher::Regex(format!(
+                r#""_key":"{}""#,
+                key_pattern
+            )))
+            .with_status(201)
+            .with_header("content-type", "application/json")
+            .with_body(
+                serde_json::to_string(&TestResponse::with_results(&[
+                    serde_json::json!({ "_key": "k", "title": "t", "name": "n", "subject": "s" }),
+                ]))
+                .unwrap(),
+            )
+            .expect(1)
+            .create()
+    };
+    std::env::set_var("ARANGO_USER_NAME", "test_user");
+    std::env::set_var("ARANGO_PASSWORD", "not_a_real_password");
+
+    let connection = ArangoConnection::with_context(
+        mockito::server_url(),
+        "test_db".to_owned(),
+        reqwest::Client::new(),
+        Context {
+            app_prefix: "api".to_owned(),
+        },
+    );
+
+    let mut app = test::init_service(
+        App::new()
+            .data(connection)
+            .configure(Page::configure)
+            .configure(Topic::configure)
+            .configure(Ticket::configure),
+    )
+    .await;
+    let post = |uri: &str, body: serde_json::Value| {
+        test::TestRequest::post()
+            .uri(uri)
+            .set_json(&body)
+            .to_request()
+    };
+
+    // Derived from the title, a key of the client is replaced
+    let m = inserted("hello-world");
+    let request = post(
+        "/page",
+        serde_json::json!({ "title": "Hello, World!", "_key": "mine" }),
+    );
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    m.assert();
+    drop(m);
+
+    let request = post("/page", serde_json::json!({ "title": "?!" }));
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Sortable, generated in Rust
+    let m = inserted("[0-9A-Z]{26}");
+    let request = post("/ticket", serde_json::json!({ "subject": "Broken" }));
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    m.assert();
+    drop(m);
+
+    // Taken from the client, after validation
+    let m = inserted("rust:2018");
+    let request = post(
+        "/topic",
+        serde_json::json!({ "name": "Rust", "_key": "rust:2018" }),
+    );
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    m.assert();
+
+    for key in &[
+        serde_json::json!(""),
+        serde_json::json!("rust/2018"),
+        serde_json::json!("r".repeat(255)),
+    ] {
+        let request = post("/topic", serde_json::json!({ "name": "Rust", "_key": key }));
+        let response = app.call(request).await.unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "{}",
+            key
+        );
+        let body: api_tools::ErrorBody =
+            serde_json::from_slice(&test::read_body(response).await).unwrap();
+        assert!(body.details.contains_key("_key"));
+    }
+}
@@ -1,5 +1,5 @@
 import { stringify } from 'query-string';
-import { fetchUtils, DataProvider } from 'ra-core';
+import { fetchUtils, DataProvider, HttpError } from 'ra-core';
 
 /**
  * Maps react-admin queries to a simple REST API
@@ -10,9 +10,9 @@ import { fetchUtils, DataProvider } from 'ra-core';
  *
  * @example
  *
- * getList     => GET http://my.api.url/post?sort=['title','ASC']&range=[0, 24]
+ * getList     => GET http://my.api.url/post?title=foo&sort=-title&offset=0&limit=25
  * getOne      => GET http://my.api.url/post/123
- * getMany     => GET http://my.api.url/post?filter={id:[123,456,789]}
+ * getMany     => GET http://my.api.url/post?_key__in=123,456,789
  * update      => PUT http://my.api.url/post/123
  * create      => POST http://my.api.url/post
  * delete      => DELETE http://my.api.url/post/123
@@ -33,14 +33,31 @@ import { fetchUtils, DataProvider } from 'ra-core';
  *
  * export default App;
  */
-export default (apiUrl: string, httpClient = fetchUtils.fetchJson): DataProvider => ({
+// react-admin sorts on `id` by default, which is mapped from `_id`.
+const sortParam = ({ field, order }: { field: string, order: string }) => {
+    const name = field === 'id' ? '_key' : field;
+    return order === 'DESC' ? `-${name}` : name;
+};
+
+// Validation errors (422) carry the violated rules of each field in `details`, show them in the notification.
+const withFieldErrors = (httpClient: typeof fetchUtils.fetchJson) =>
+    (url: string, options?: fetchUtils.Options) => httpClient(url, options).catch(error => {
+        const details = error.body && error.body.details;
+        if (error.status === 422 && details) {
+            const fields = Object.keys(details).map(field =>
+                `${field}: ${details[field].map((rule: any) => rule.message || rule.code).join(', ')}`
+            );
+            throw new HttpError(fields.join('; '), error.status, error.body);
+        }
+        throw error;
+    });
+
+export default (apiUrl: string, httpClient = withFieldErrors(fetchUtils.fetchJson)): DataProvider => ({
     getList: (resource, params) => {
         const { page, perPage } = params.pagination;
-        const { field, order } = params.sort;
         const query = {
             ...fetchUtils.flattenObject(params.filter),
-            sort: field,
-            order: order,
+            sort: sortParam(params.sort),
             offset: (page - 1) * perPage,
             limit: perPage,
         };
@@ -52,7 +69,7 @@ export default (apiUrl: string, httpClient = fetchUtils.fetchJson): DataProvider
             //         'The X-Total-Count header is missing in the HTTP Response. The api_client Data Provider expects responses for lists of resources to contain this header with the total number of results to build the pagination. If you are using CORS, did you declare X-Total-Count in the Access-Control-Expose-Headers header?'
             //     );
             // }
-            let total_count = headers.get('x-total-count') || '100';
+            let total_count = headers.get('x-total-count') || String(json.total);
 
             let reslut = json.collection.map((e: any) => {
                 let result = {
@@ -91,7 +108,7 @@ export default (apiUrl: string, httpClient = fetchUtils.fetchJson): DataProvider
 
     getMany: (resource, params) => {
         const query = {
-            id: params.ids,
+            _key__in: params.ids.map(id => String(id).split('/').pop()).join(','),
         };
         const url = `${apiUrl}/${resource}?${stringify(query)}`;
         return httpClient(url).then(({ json }) => {
@@ -110,12 +127,10 @@ export default (apiUrl: string, httpClient = fetchUtils.fetchJson): DataProvider
 
     getManyReference: (resource, params) => {
         const { page, perPage } = params.pagination;
-        const { field, order } = params.sort;
         const query = {
             ...fetchUtils.flattenObject(params.filter),
             [params.target]: params.id,
-            sort: field,
-            order: order,
+            sort: sortParam(params.sort),
             offset: (page - 1) * perPage,
             limit: perPage,
         };
@@ -127,7 +142,7 @@ export default (apiUrl: string, httpClient = fetchUtils.fetchJson): DataProvider
             //         'The X-Total-Count header is missing in the HTTP Response. The api_client Data Provider expects responses for lists of resources to contain this header with the total number of results to build the pagination. If you are using CORS, did you declare X-Total-Count in the Access-Control-Expose-Headers header?'
             //     );
             // }
-            let total_count = headers.get('x-total-count') || '100';
+            let total_count = headers.get('x-total-count') || String(json.total);
 
             let result = json.collection.map((e: any) => {
                 let res = {